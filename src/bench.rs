@@ -0,0 +1,168 @@
+use crate::entity::{
+    objects::Asteroid,
+    physics::{CollisionEvent, PhysicsSystem, RigidBodySystem},
+    Collider, ColliderShape, Hitbox, PhysicsWorld, RigidBody, Transform,
+};
+use cgmath::{Vector2, Vector3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use specs::{prelude::*, shrev::EventChannel};
+use std::time::{Duration, Instant};
+
+/// The number of asteroids, and of blocks, that the physics is benchmarked with
+const SIZES: [usize; 4] = [500, 1000, 2000, 4000];
+const RAYCASTS: usize = 1000;
+
+/// Times the physics with thousands of asteroids drifting over a field of blocks, and prints
+/// how long each part takes as the number of colliders grows. Best run with `--release`.
+///
+/// Usage: `--bench [ticks]`
+pub fn run_benchmarks(args: &[String]) {
+    let ticks = args
+        .first()
+        .and_then(|ticks| ticks.parse().ok())
+        .unwrap_or(120);
+
+    println!("[Bench] Averaged over {} ticks", ticks);
+    for &size in SIZES.iter() {
+        benchmark(size, size, ticks);
+    }
+}
+
+fn benchmark(asteroids: usize, blocks: usize, ticks: u32) {
+    let mut world = World::new();
+    world.register::<Transform>();
+    world.register::<RigidBody>();
+    world.register::<Collider>();
+    world.insert(PhysicsWorld::new());
+    // The game reads every collision event, so the benchmark drains them too
+    let mut collisions = EventChannel::<CollisionEvent>::new();
+    let mut collision_reader = collisions.register_reader();
+    world.insert(collisions);
+    let physics_system = PhysicsSystem::new(&mut world);
+    let mut dispatcher = DispatcherBuilder::new()
+        .with(RigidBodySystem, "rigid_body_system", &[])
+        .with(physics_system, "physics_system", &["rigid_body_system"])
+        .build();
+
+    // The blocks are laid out in a square like a ship, in the middle of the asteroids
+    let mut rng = StdRng::seed_from_u64(0);
+    let side = (blocks as f32).sqrt().ceil() as usize;
+    let extent = side as f32 * 2.0;
+    let offset = (extent - side as f32) / 2.0;
+    for i in 0..blocks {
+        let (x, y) = ((i % side) as f32 + offset, (i / side) as f32 + offset);
+        world
+            .create_entity()
+            .with(Transform::from_position(x, y, 0.0))
+            .with(Collider::new(
+                Hitbox::with_shape(ColliderShape::Cuboid(Vector3::new(1.0, 1.0, 1.0))),
+                Collider::SHIP,
+                vec![Collider::ASTEROID],
+            ))
+            .build();
+    }
+    for _ in 0..asteroids {
+        let angle = rng.gen_range(0.0..crate::PI * 2.0);
+        world
+            .create_entity()
+            .with(Transform::from_position(
+                rng.gen_range(0.0..extent),
+                rng.gen_range(0.0..extent),
+                0.0,
+            ))
+            .with(RigidBody {
+                velocity: Vector3::new(angle.cos(), angle.sin(), 0.0) * Asteroid::VELOCITY,
+            })
+            .with(Collider::new(
+                Hitbox::with_shape(ColliderShape::Sphere(Asteroid::COLLIDER_RADIUS)),
+                Collider::ASTEROID,
+                vec![Collider::SHIP, Collider::MISSLE],
+            ))
+            .build();
+    }
+
+    // Every collider is added to the physics world on the first update
+    let start = Instant::now();
+    update(&mut dispatcher, &world, &mut collision_reader);
+    let first_update = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..ticks {
+        update(&mut dispatcher, &world, &mut collision_reader);
+    }
+    let update = start.elapsed() / ticks;
+
+    // For comparison, build the whole physics world again every update
+    let start = Instant::now();
+    for _ in 0..ticks {
+        let (entities, colliders, transforms) =
+            world.system_data::<(Entities, ReadStorage<Collider>, ReadStorage<Transform>)>();
+        let mut rebuilt = PhysicsWorld::new();
+        for (entity, collider, transform) in (&entities, &colliders, &transforms).join() {
+            rebuilt.insert(entity, collider, transform);
+        }
+        rebuilt.update();
+    }
+    let rebuild = start.elapsed() / ticks;
+
+    let physics_world = world.fetch::<PhysicsWorld>();
+    let targets: Vec<Vector2<f32>> = (0..RAYCASTS)
+        .map(|_| Vector2::new(rng.gen_range(0.0..extent), rng.gen_range(0.0..extent)))
+        .collect();
+    let start = Instant::now();
+    let hits = targets
+        .iter()
+        .filter_map(|target| {
+            physics_world.raycast(
+                Vec::new(),
+                Vector3::new(target.x, target.y, 10.0),
+                Vector3::new(target.x, target.y, -10.0),
+            )
+        })
+        .count();
+    let raycast = start.elapsed() / RAYCASTS as u32;
+
+    println!(
+        "[Bench] {} asteroids, {} blocks ({} colliders): first update {}, update {}, \
+         rebuilding every update {}, raycast {} ({} of {} hit)",
+        asteroids,
+        blocks,
+        physics_world.len(),
+        format_duration(first_update),
+        format_duration(update),
+        format_duration(rebuild),
+        format_duration(raycast),
+        hits,
+        RAYCASTS
+    );
+}
+
+fn update(
+    dispatcher: &mut Dispatcher,
+    world: &World,
+    collision_reader: &mut ReaderId<CollisionEvent>,
+) {
+    dispatcher.dispatch(world);
+    world
+        .fetch::<EventChannel<CollisionEvent>>()
+        .read(collision_reader)
+        .for_each(drop);
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_millis(1) {
+        format!("{:.1}us", duration.as_secs_f64() * 1_000_000.0)
+    } else {
+        format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn benchmark_runs() {
+        benchmark(20, 20, 2);
+    }
+}
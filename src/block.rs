@@ -1,35 +1,144 @@
 use crate::entity::{
-    objects::{self, Health, ObjectMeshes},
-    ColliderShape, Hitbox, InputAction, InputManager, Line, RaycastWorld, Transform,
+    crew::Crew,
+    hierarchy,
+    jobs::{Construction, OperatorStation},
+    objects::{Asteroid, Health},
+    power::PowerConsumer,
+    projectile::{self, Guidance, Projectile, Projectiles},
+    ship, BlockEntity, Collider, ColliderShape, Hitbox, InputAction, InputManager, Line, Model,
+    Parent, PhysicsWorld, PlayerShip, Ship, Transform,
 };
-use crate::graphics::{self, Mesh, MeshId, MeshManager};
-use cgmath::{Point2, Vector3};
+use crate::graphics::{self, MeshId, MeshManager};
+use crate::item::GameItem;
+use cgmath::{prelude::*, Point2, Point3, Quaternion, Rad, Vector2, Vector3};
+use serde::{Deserialize, Serialize};
 use specs::{prelude::*, world::LazyBuilder, Component};
+use std::collections::HashMap;
+use std::f32::consts::PI;
 
 pub type BlockId = usize;
 pub type OnBlockSetup = fn(LazyBuilder) -> LazyBuilder;
 
+pub const BLOCKS_PATH: &str = "assets/blocks.ron";
+
+/// The behaviours that block definitions can refer to by name
+const BEHAVIOURS: &[(&str, OnBlockSetup)] = &[
+    ("miner", setup_miner),
+    ("laser", setup_laser),
+    ("door", setup_door),
+];
+
 // TODO: Currently size is used for collision and grid spaces (but they should seperate)
 pub struct Block {
     pub id: BlockId,
-    pub type_name: &'static str,
+    pub type_name: String,
     pub mesh_id: MeshId,
+    /// Darker copies of the mesh, shown as the block gets more damaged
+    pub damaged_mesh_ids: Vec<MeshId>,
+    /// A blue copy of the mesh, shown while the block is waiting to be built
+    pub construction_mesh_id: MeshId,
     /// The Size of the block in terms of grid spaces (x, y)
     pub size: Point2<u16>,
     /// The height of the block (z)
     pub height: f32,
     pub hitbox: Hitbox,
-    pub setup: Option<OnBlockSetup>,
+    pub setup: Vec<OnBlockSetup>,
     pub is_gadget: bool,
+    /// Blocks that can't reach a core block break away from the ship
+    pub is_core: bool,
+    /// Airtight blocks seal the edges of rooms
+    pub is_airtight: bool,
+    /// The amount of damage the block can take before it is destroyed
+    pub health: u32,
+    /// Power added to the block's grid every second
+    pub power_generation: f32,
+    /// Power drawn from the block's grid every second while the block is working
+    pub power_usage: f32,
+    /// The amount of power the block can store for its grid
+    pub battery_capacity: f32,
+    /// How heavy the block is, which makes the ship harder to push and turn
+    pub mass: f32,
+    /// The force the block pushes its ship with while it is firing
+    pub thrust: f32,
+    /// Seconds of crew work needed to build the block
+    pub build_time: f32,
+    /// Blocks that need an operator only work while a crew member is manning them
+    pub needs_operator: bool,
+    /// The items used to build this block. These are refunded when the block is removed.
+    pub cost: Vec<(GameItem, u32)>,
+}
+
+impl Block {
+    /// The number of damaged meshes each block has
+    const DAMAGE_STAGES: u32 = 2;
+
+    /// The mesh that shows how damaged the block is
+    pub fn mesh_for_health(&self, health: u32) -> MeshId {
+        // Each stage covers an equal share of the block's health
+        let damage = self.health.saturating_sub(health);
+        let stage = (damage * (Self::DAMAGE_STAGES + 1) / self.health.max(1)) as usize;
+
+        match stage {
+            0 => self.mesh_id,
+            stage => self.damaged_mesh_ids[(stage - 1).min(self.damaged_mesh_ids.len() - 1)],
+        }
+    }
+}
+
+/// A block as it is written in the blocks file
+#[derive(Deserialize)]
+struct BlockDefinition {
+    name: String,
+    mesh: String,
+    #[serde(default)]
+    mesh_scale: Option<(f32, f32, f32)>,
+    size: (u16, u16),
+    height: f32,
+    health: u32,
+    #[serde(default)]
+    hitbox: Option<HitboxDefinition>,
+    #[serde(default)]
+    gadget: bool,
+    #[serde(default)]
+    core: bool,
+    #[serde(default)]
+    airtight: bool,
+    #[serde(default)]
+    power_generation: f32,
+    #[serde(default)]
+    power_usage: f32,
+    #[serde(default)]
+    battery_capacity: f32,
+    #[serde(default = "default_mass")]
+    mass: f32,
+    #[serde(default)]
+    thrust: f32,
+    #[serde(default = "default_build_time")]
+    build_time: f32,
+    #[serde(default)]
+    operated: bool,
+    #[serde(default)]
+    behaviours: Vec<String>,
+    #[serde(default)]
+    cost: Vec<(GameItem, u32)>,
+}
+
+fn default_mass() -> f32 {
+    1.0
+}
+
+fn default_build_time() -> f32 {
+    2.0
+}
+
+#[derive(Deserialize)]
+struct HitboxDefinition {
+    size: (f32, f32, f32),
+    offset: (f32, f32, f32),
 }
 
 pub struct Blocks {
     blocks: Vec<Block>,
-    pub wall: BlockId,
-    pub engine: BlockId,
-    pub cube: BlockId,
-    pub miner: BlockId,
-    pub laser: BlockId,
 }
 
 impl Blocks {
@@ -38,97 +147,125 @@ impl Blocks {
             .get(id)
             .unwrap_or_else(|| panic!("Invalid block ID:  {}", id))
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.iter()
+    }
+
+    pub fn find_block(&self, type_name: &str) -> Option<BlockId> {
+        self.blocks
+            .iter()
+            .find(|block| block.type_name == type_name)
+            .map(|block| block.id)
+    }
 }
 
-pub fn load_blocks(device: &wgpu::Device, mesh_manager: &mut MeshManager) -> Blocks {
-    let mut blocks = Vec::new();
-    let mut register_mesh = |mesh: &Mesh| {
-        let id = mesh_manager.add(device, mesh);
-        mesh_manager.set_mesh_visisble(id, crate::RENDER_BLOCKS);
-        id
-    };
+/// Loads every block from the blocks file. Panics if the file is missing or invalid,
+/// since the game can't run without its blocks.
+pub fn load_blocks(mesh_manager: &mut MeshManager) -> Blocks {
+    let text = std::fs::read_to_string(BLOCKS_PATH)
+        .unwrap_or_else(|err| panic!("Unable to load {}: {}", BLOCKS_PATH, err));
+    let definitions: Vec<BlockDefinition> = ron::de::from_str(&text)
+        .unwrap_or_else(|err| panic!("Invalid block file {}: {}", BLOCKS_PATH, err));
 
-    let wall = create_block(
-        &mut blocks,
-        register_mesh(&graphics::load_mesh("wall")),
-        (1, 1, 3.0),
-        None,
-        "wall",
-        None,
-        false,
-    );
-    let engine = create_block(
-        &mut blocks,
-        register_mesh(&graphics::load_mesh("engine")),
-        (1, 1, 1.0),
-        None,
-        "engine",
-        None,
-        false,
-    );
-    let cube = create_block(
-        &mut blocks,
-        register_mesh(&graphics::load_mesh("box")),
-        (1, 1, 1.0),
-        None,
-        "Box",
-        None,
-        false,
-    );
-    let miner = create_block(
-        &mut blocks,
-        register_mesh(&graphics::load_mesh("miner")),
-        (1, 1, 1.0),
-        None,
-        "Miner",
-        Some(setup_miner),
-        false,
-    );
-    let laser = create_block(
-        &mut blocks,
-        register_mesh(&graphics::load_mesh("laser")),
-        (1, 1, 0.2),
-        Some(Hitbox::new(
-            ColliderShape::Cuboid(Vector3::new(0.6, 0.6, 0.525)),
-            Vector3::new(0.0, 0.0, 0.525 / 2.0),
-        )),
-        "Laser",
-        Some(setup_laser),
-        true,
-    );
+    let mut blocks = Vec::new();
+    for definition in definitions {
+        if blocks
+            .iter()
+            .any(|block: &Block| block.type_name == definition.name)
+        {
+            panic!("Block {} is defined more than once", definition.name);
+        }
 
-    Blocks {
-        blocks,
-        wall,
-        engine,
-        cube,
-        miner,
-        laser,
+        let mut mesh = graphics::load_mesh(&definition.mesh);
+        if let Some((x, y, z)) = definition.mesh_scale {
+            mesh.scale(x, y, z);
+        }
+        let mesh_id = mesh_manager.add(&mesh);
+        mesh_manager.set_mesh_visisble(mesh_id, crate::RENDER_BLOCKS);
+
+        let mut construction_mesh = mesh.clone();
+        construction_mesh.tint(Point3::new(0.2, 0.4, 1.0));
+        construction_mesh.name = format!("{} (construction)", definition.name);
+        let construction_mesh_id = mesh_manager.add(&construction_mesh);
+        mesh_manager.set_mesh_visisble(construction_mesh_id, crate::RENDER_BLOCKS);
+
+        let damaged_mesh_ids = (0..Block::DAMAGE_STAGES)
+            .map(|stage| {
+                mesh.tint(Point3::new(0.1, 0.1, 0.1));
+                mesh.name = format!("{} (damage stage {})", definition.name, stage + 1);
+                let damaged_mesh_id = mesh_manager.add(&mesh);
+                mesh_manager.set_mesh_visisble(damaged_mesh_id, crate::RENDER_BLOCKS);
+                damaged_mesh_id
+            })
+            .collect();
+
+        create_block(
+            &mut blocks,
+            mesh_id,
+            damaged_mesh_ids,
+            construction_mesh_id,
+            definition,
+        );
     }
+
+    Blocks { blocks }
 }
 
 fn create_block(
     blocks: &mut Vec<Block>,
     mesh_id: MeshId,
-    size: (u16, u16, f32),
-    hitbox: Option<Hitbox>,
-    type_name: &'static str,
-    setup: Option<OnBlockSetup>,
-    is_gadget: bool,
+    damaged_mesh_ids: Vec<MeshId>,
+    construction_mesh_id: MeshId,
+    definition: BlockDefinition,
 ) -> BlockId {
     let id = blocks.len();
+    let (width, length) = definition.size;
+    let height = definition.height;
+    let setup = definition
+        .behaviours
+        .iter()
+        .map(|name| {
+            BEHAVIOURS
+                .iter()
+                .find(|(behaviour, _)| behaviour == name)
+                .map(|(_, setup)| *setup)
+                .unwrap_or_else(|| {
+                    panic!("Block {} has unknown behaviour: {}", definition.name, name)
+                })
+        })
+        .collect();
+    let hitbox = match definition.hitbox {
+        Some(HitboxDefinition { size, offset }) => {
+            Hitbox::new(ColliderShape::Cuboid(size.into()), offset.into())
+        }
+        None => Hitbox::new(
+            ColliderShape::Cuboid(Vector3::new(width as f32, length as f32, height)),
+            Vector3::new(0.0, 0.0, height / 2.0),
+        ),
+    };
     let block = Block {
         id,
         mesh_id,
-        type_name,
+        damaged_mesh_ids,
+        construction_mesh_id,
+        type_name: definition.name,
         setup,
-        is_gadget,
-        hitbox: hitbox.unwrap_or(Hitbox::new(
-            ColliderShape::Cuboid(Vector3::new(size.0 as f32, size.1 as f32, size.2)),
-            Vector3::new(0.0, 0.0, size.2 / 2.0),
-        )),
-        size: Point2::new(size.0, size.1),
-        height: size.2,
+        is_gadget: definition.gadget,
+        is_core: definition.core,
+        is_airtight: definition.airtight,
+        health: definition.health,
+        power_generation: definition.power_generation,
+        power_usage: definition.power_usage,
+        battery_capacity: definition.battery_capacity,
+        mass: definition.mass,
+        thrust: definition.thrust,
+        build_time: definition.build_time,
+        needs_operator: definition.operated,
+        cost: definition.cost,
+        hitbox,
+        size: Point2::new(width, length),
+        height,
     };
 
     println!("[Registered Block] {}={}", &block.type_name, id);
@@ -139,27 +276,108 @@ fn create_block(
 pub fn register_components(world: &mut World) {
     world.register::<Miner>();
     world.register::<Laser>();
+    world.register::<Door>();
 }
 
 pub fn setup_systems(dispatcher: &mut DispatcherBuilder) {
     dispatcher.add(MinerSystem, "", &[]);
     dispatcher.add(LaserSystem, "", &[]);
+    dispatcher.add(BlockDamageSystem, "", &[]);
+    dispatcher.add(DoorSystem::default(), "", &[]);
+}
+
+/// Swaps the meshes of damaged blocks, so that the damage can be seen on the ship.
+/// Blocks that haven't been built yet are shown as blueprints instead.
+pub struct BlockDamageSystem;
+
+impl<'a> System<'a> for BlockDamageSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Blocks>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Construction>,
+        WriteStorage<'a, Model>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, blocks, block_entities, healths, constructions, mut models) = data;
+
+        for (entity, block_entity, health) in (&entities, &block_entities, &healths).join() {
+            let block = blocks.get_block(block_entity.block_id());
+            let mesh_id = if constructions.contains(entity) {
+                block.construction_mesh_id
+            } else {
+                block.mesh_for_health(health.health())
+            };
+
+            // Only write to models that change, since writing marks them as modified
+            if models
+                .get(entity)
+                .is_some_and(|model| model.mesh_id != mesh_id)
+            {
+                models.get_mut(entity).unwrap().mesh_id = mesh_id;
+            }
+        }
+    }
 }
 
 fn setup_miner(builder: LazyBuilder) -> LazyBuilder {
     builder.with(Miner::default())
 }
 
+/// How a miner picks its targets and fires at them, which can be changed for each miner
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MinerSettings {
+    /// Automatic miners fire at asteroids by themselves, instead of at the ones the player clicks on
+    pub automatic: bool,
+    /// Seconds of reloading between salvos, at full power
+    pub cooldown: f32,
+    /// The most missles launched at once, each at a different asteroid
+    pub salvo_size: u32,
+    /// Automatic miners only go after asteroids of this ore, or any ore if there isn't one
+    pub ore: Option<GameItem>,
+}
+
+impl Default for MinerSettings {
+    fn default() -> Self {
+        Self {
+            automatic: false,
+            cooldown: 2.0,
+            salvo_size: 1,
+            ore: None,
+        }
+    }
+}
+
 #[derive(Component, Default)]
 #[storage(HashMapStorage)]
 pub struct Miner {
-    shoot_time: u16,
+    /// Seconds of reloading towards the next salvo, which slows down when the miner lacks power
+    reload: f32,
+    pub settings: MinerSettings,
+    /// The asteroids in range that an automatic miner will fire at next, nearest first
+    queue: Vec<Entity>,
 }
 
 impl Miner {
-    const TOTAL_TIME: u16 = 120;
+    /// How far away automatic miners look for asteroids
+    const RANGE: f32 = 25.0;
+
+    pub fn queue(&self) -> &[Entity] {
+        &self.queue
+    }
+
+    fn is_loaded(&self) -> bool {
+        self.reload >= self.settings.cooldown
+    }
 }
 
+/// Launches mining missles from every manned miner once it has reloaded.
+///
+/// Manual miners fire at the asteroid the player is holding the mouse over while mining. Automatic
+/// miners keep a queue of the asteroids in range, and fire a salvo at the front of it. Asteroids that
+/// already have enough damage on the way to break them apart aren't fired at again.
 pub struct MinerSystem;
 
 impl<'a> System<'a> for MinerSystem {
@@ -167,95 +385,585 @@ impl<'a> System<'a> for MinerSystem {
         Entities<'a>,
         Read<'a, LazyUpdate>,
         ReadExpect<'a, InputManager>,
-        ReadExpect<'a, ObjectMeshes>,
+        ReadExpect<'a, Projectiles>,
         WriteStorage<'a, Miner>,
-        WriteStorage<'a, Transform>,
+        WriteStorage<'a, PowerConsumer>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Construction>,
+        ReadStorage<'a, OperatorStation>,
+        ReadStorage<'a, Asteroid>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Projectile>,
+        ReadStorage<'a, Guidance>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, lazy_update, input, meshes, mut miners, mut transforms) = data;
-
-        for (transform, miner) in (&mut transforms, &mut miners).join() {
-            transform.set_rotation_z(crate::PI);
-
-            if miner.shoot_time > Miner::TOTAL_TIME {
-                if input.action == InputAction::Mining {
-                    if let Some(target) = input.target {
-                        let position = transform.position + Vector3::new(0.0, 0.0, 0.5);
-                        let builder = lazy_update.create_entity(&entities);
-                        objects::build_mining_missle(&meshes, builder, target, position);
-                        miner.shoot_time = 0;
-                    }
-                }
+        let (
+            entities,
+            lazy_update,
+            input,
+            definitions,
+            mut miners,
+            mut consumers,
+            transforms,
+            constructions,
+            stations,
+            asteroids,
+            healths,
+            projectiles,
+            guidances,
+        ) = data;
+        let dt = 1.0 / 60.0;
+
+        // The damage on its way to each asteroid, including from missles launched this tick
+        let mut incoming: HashMap<Entity, u32> = HashMap::new();
+        for (projectile, guidance) in (&projectiles, &guidances).join() {
+            if let Some(target) = guidance.target {
+                *incoming.entry(target).or_default() += projectile.payload.damage;
+            }
+        }
+        let needs_missles = |incoming: &HashMap<Entity, u32>, target: Entity| {
+            let health = healths.get(target).map_or(0, Health::health);
+            incoming.get(&target).copied().unwrap_or(0) < health
+        };
+        let clicked = input
+            .target
+            .filter(|target| input.action == InputAction::Mining && asteroids.contains(*target));
+
+        for (transform, miner, consumer, _, station) in (
+            &transforms,
+            &mut miners,
+            (&mut consumers).maybe(),
+            !&constructions,
+            stations.maybe(),
+        )
+            .join()
+        {
+            if !station.is_none_or(OperatorStation::is_manned) {
+                continue;
+            }
+
+            if miner.settings.automatic {
+                let in_range = |target: Vector3<f32>| {
+                    (target - transform.position).truncate().magnitude() <= Miner::RANGE
+                };
+                let mut queue: Vec<(Entity, f32)> = (&entities, &asteroids, &transforms)
+                    .join()
+                    .filter(|(entity, asteroid, target)| {
+                        needs_missles(&incoming, *entity)
+                            && miner.settings.ore.is_none_or(|ore| ore == asteroid.0)
+                            && in_range(target.position)
+                    })
+                    .map(|(entity, _, target)| {
+                        (entity, (target.position - transform.position).magnitude())
+                    })
+                    .collect();
+                queue.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+                miner.queue = queue.into_iter().map(|(entity, _)| entity).collect();
             } else {
-                miner.shoot_time += 1;
+                miner.queue = clicked
+                    .filter(|target| needs_missles(&incoming, *target))
+                    .into_iter()
+                    .collect();
+            }
+
+            if !miner.is_loaded() {
+                // Reloading uses power, and runs at full speed for miners that don't need any
+                miner.reload += consumer.map_or(1.0, PowerConsumer::draw) * dt;
+                continue;
+            }
+
+            let salvo: Vec<Entity> = miner
+                .queue
+                .iter()
+                .copied()
+                .take(miner.settings.salvo_size as usize)
+                .collect();
+            if salvo.is_empty() {
+                continue;
+            }
+
+            let missle = definitions
+                .get(projectile::MINING_MISSLE)
+                .unwrap_or_else(|| {
+                    panic!("Projectile {} is not defined", projectile::MINING_MISSLE)
+                });
+
+            // Missles are launched upwards, leaning out of the front of the miner,
+            // and fanned out to the sides when there are several of them
+            let forward = transform.rotation.rotate_vector(Vector3::unit_x());
+            let side = transform.rotation.rotate_vector(Vector3::unit_y());
+            let position = transform.position + Vector3::new(0.0, 0.0, 0.5);
+            for (i, target) in salvo.iter().enumerate() {
+                let spread = i as f32 - (salvo.len() - 1) as f32 / 2.0;
+                let direction = Vector3::unit_z() + forward * 0.5 + side * spread * 0.3;
+                let builder = lazy_update.create_entity(&entities);
+                projectile::launch_projectile(missle, builder, Some(*target), position, direction);
+                *incoming.entry(*target).or_default() += missle.payload.damage;
             }
+            miner.queue.drain(..salvo.len());
+            miner.reload = 0.0;
         }
     }
 }
 
 fn setup_laser(builder: LazyBuilder) -> LazyBuilder {
-    builder.with(Laser)
+    builder.with(Laser::default())
 }
 
-#[derive(Component)]
+/// How a laser picks which asteroid to shoot at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TargetMode {
+    /// Only shoots at asteroids that the player clicks on
+    #[default]
+    Manual,
+    /// Shoots at the closest asteroid
+    Nearest,
+    /// Finishes off the most damaged asteroid first
+    LowestHealth,
+    /// Goes after the asteroid with the most valuable ore, closest first
+    MostValuable,
+}
+
+impl TargetMode {
+    /// The mode after this one, for cycling through them
+    pub fn next(self) -> Self {
+        match self {
+            TargetMode::Manual => TargetMode::Nearest,
+            TargetMode::Nearest => TargetMode::LowestHealth,
+            TargetMode::LowestHealth => TargetMode::MostValuable,
+            TargetMode::MostValuable => TargetMode::Manual,
+        }
+    }
+}
+
+/// A gadget that turns towards its own target, and burns it down once it is aimed
+#[derive(Component, Default)]
 #[storage(HashMapStorage)]
-pub struct Laser;
+pub struct Laser {
+    /// Damage built up from the power that the laser has drawn, dealt a point at a time
+    charge: f32,
+    pub mode: TargetMode,
+    /// The asteroid that the laser is aiming at
+    pub target: Option<Entity>,
+    /// Ticks until a laser that couldn't see anything to shoot at looks again
+    search_delay: u32,
+}
+
+impl Laser {
+    /// How far away lasers can pick and hit targets from
+    const RANGE: f32 = 20.0;
+    /// How quickly lasers turn, in radians per second
+    const TURN_RATE: f32 = 3.0;
+    /// Lasers only fire once they point this close to their target, in radians
+    const AIM_TOLERANCE: f32 = 0.05;
+    /// How many ticks a laser waits after finding nothing in sight before it looks again
+    const SEARCH_INTERVAL: u32 = 10;
+    /// Where the beam comes out of, above the block and out from its centre
+    const HEIGHT: f32 = 0.4;
+    const RADIUS: f32 = 0.35;
+
+    /// Where the beam starts from when the laser points at the angle
+    fn muzzle(position: Vector3<f32>, angle: f32) -> Vector3<f32> {
+        // The laser's mesh points away from its angle, so the beam comes out of the other side
+        position + Vector3::new(0.0, 0.0, Self::HEIGHT)
+            - Self::RADIUS * Vector3::new(angle.cos(), angle.sin(), 0.0)
+    }
+
+    /// The angle that a laser at the position has to point at to face the target
+    fn aim_angle(position: Vector3<f32>, target: Vector3<f32>) -> f32 {
+        (position.y - target.y).atan2(position.x - target.x)
+    }
 
+    /// Picks the asteroid to shoot at out of the ones within range, which are given with
+    /// their distance, health and ore. Line of sight is only checked in order of preference,
+    /// so that the laser stops looking once it has found one that it can see.
+    fn pick_target(
+        mode: TargetMode,
+        mut candidates: Vec<(Entity, f32, u32, GameItem)>,
+        in_sight: impl Fn(Entity) -> bool,
+    ) -> Option<Entity> {
+        // Ties are broken by distance, so that lasers don't turn further than they need to
+        let key = |(_, distance, health, item): &(Entity, f32, u32, GameItem)| match mode {
+            TargetMode::Manual | TargetMode::Nearest => (0, *distance),
+            TargetMode::LowestHealth => (*health, *distance),
+            TargetMode::MostValuable => (u32::MAX - item.value(), *distance),
+        };
+
+        if mode == TargetMode::Manual {
+            return None;
+        }
+        candidates.sort_by(|a, b| {
+            let (a, b) = (key(a), key(b));
+            a.0.cmp(&b.0).then(a.1.total_cmp(&b.1))
+        });
+        candidates
+            .into_iter()
+            .map(|(entity, ..)| entity)
+            .find(|entity| in_sight(*entity))
+    }
+}
+
+/// Turns every manned laser towards its target, and fires once it is aimed.
+///
+/// Clicking on an asteroid while lasering hands it to the manual lasers on the player's ship.
+/// The other lasers pick their own targets from the asteroids they can see within range,
+/// and keep shooting at them until they are out of sight. Lasers that can't see anything
+/// only look again every few ticks, since every asteroid they check takes a raycast.
 pub struct LaserSystem;
 
 impl<'a> System<'a> for LaserSystem {
     type SystemData = (
         Entities<'a>,
         ReadExpect<'a, InputManager>,
-        ReadExpect<'a, RaycastWorld>,
+        ReadExpect<'a, PhysicsWorld>,
+        ReadExpect<'a, PlayerShip>,
         WriteStorage<'a, Laser>,
+        WriteStorage<'a, PowerConsumer>,
         WriteStorage<'a, Line>,
         WriteStorage<'a, Health>,
         WriteStorage<'a, Transform>,
+        WriteStorage<'a, Parent>,
+        ReadStorage<'a, Construction>,
+        ReadStorage<'a, OperatorStation>,
+        ReadStorage<'a, Asteroid>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, input, raycaster, lasers, mut lines, mut healths, mut transforms) = data;
-
-        if input.action != InputAction::Laser {
-            return;
-        }
+        let (
+            entities,
+            input,
+            raycaster,
+            player_ship,
+            mut lasers,
+            mut consumers,
+            mut lines,
+            mut healths,
+            mut transforms,
+            mut parents,
+            constructions,
+            stations,
+            asteroids,
+        ) = data;
+        let dt = 1.0 / 60.0;
+
+        let clicked = input
+            .target
+            .filter(|target| input.action == InputAction::Laser && asteroids.contains(*target));
+        let targets: Vec<(Entity, Vector3<f32>, u32, GameItem)> =
+            (&entities, &asteroids, &transforms, &healths)
+                .join()
+                .map(|(entity, asteroid, transform, health)| {
+                    (entity, transform.position, health.health(), asteroid.0)
+                })
+                .collect();
+
+        for (entity, laser, consumer, _, station) in (
+            &entities,
+            &mut lasers,
+            (&mut consumers).maybe(),
+            !&constructions,
+            stations.maybe(),
+        )
+            .join()
+        {
+            let (position, rotation) = match transforms.get(entity) {
+                Some(transform) => (transform.position, transform.rotation),
+                None => continue,
+            };
+            let in_range = |target_pos: Vector3<f32>| {
+                (target_pos - position).truncate().magnitude() <= Laser::RANGE
+            };
+            let in_sight = |target: Entity, target_pos: Vector3<f32>| {
+                let start = Laser::muzzle(position, Laser::aim_angle(position, target_pos));
+                raycaster.raycast(Vec::with_capacity(0), start, target_pos) == Some(target)
+            };
+            let find = |target: Entity| targets.iter().find(|(other, ..)| *other == target);
+
+            if let (TargetMode::Manual, Some(clicked)) = (laser.mode, clicked) {
+                if hierarchy::root(&parents, entity) == player_ship.0 {
+                    laser.target = Some(clicked);
+                }
+            }
+            // Targets that have been destroyed or gone out of range are forgotten
+            laser.target = laser
+                .target
+                .filter(|target| find(*target).is_some_and(|(_, pos, ..)| in_range(*pos)));
+            if laser.mode != TargetMode::Manual
+                && !laser
+                    .target
+                    .and_then(find)
+                    .is_some_and(|(target, pos, ..)| in_sight(*target, *pos))
+            {
+                laser.target = None;
+                if laser.search_delay > 0 {
+                    laser.search_delay -= 1;
+                } else {
+                    let candidates = targets
+                        .iter()
+                        .filter(|(_, pos, ..)| in_range(*pos))
+                        .map(|(target, pos, health, item)| {
+                            (*target, (pos - position).magnitude(), *health, *item)
+                        })
+                        .collect();
+                    laser.target = Laser::pick_target(laser.mode, candidates, |target| {
+                        find(target).is_some_and(|(_, pos, ..)| in_sight(target, *pos))
+                    });
+                    if laser.target.is_none() {
+                        laser.search_delay = Laser::SEARCH_INTERVAL;
+                    }
+                }
+            }
 
-        for (entity, _) in (&entities, &lasers).join() {
-            if let Some(target) = input.target {
-                let target_pos = transforms.get(target).unwrap().position;
-                let transform = transforms.get_mut(entity).unwrap();
-                let mut start_pos = transform.position + Vector3::new(0.0, 0.0, 0.4);
-                let angle_xy = (start_pos.y - target_pos.y).atan2(start_pos.x - target_pos.x);
-                let radius = 0.35;
-                start_pos -= radius * Vector3::new(angle_xy.cos(), angle_xy.sin(), 0.0);
-
-                let raycast = raycaster.raycast(Vec::with_capacity(0), start_pos, target_pos);
-
-                if Some(target) == raycast {
-                    transform.set_rotation_z(angle_xy);
-
-                    lines
-                        .insert(
-                            entity,
-                            Line {
-                                pt: start_pos,
-                                pt2: target_pos,
-                                color: Vector3::new(1.0, 0.0, 0.0),
-                            },
-                        )
-                        .expect("Unable to set line component for laser!");
+            let manned = station.is_none_or(OperatorStation::is_manned);
+            let (target, target_pos) = match laser.target.and_then(find) {
+                Some((target, target_pos, ..)) if manned => (*target, *target_pos),
+                _ => {
+                    lines.remove(entity);
+                    continue;
+                }
+            };
+
+            // Turn towards the target, the shortest way around
+            let forward = rotation.rotate_vector(Vector3::unit_x());
+            let angle = forward.y.atan2(forward.x);
+            let desired = Laser::aim_angle(position, target_pos);
+            let offset = (desired - angle + PI).rem_euclid(2.0 * PI) - PI;
+            let turn = offset.clamp(-Laser::TURN_RATE * dt, Laser::TURN_RATE * dt);
+            let angle = angle + turn;
+            if turn != 0.0 {
+                transforms.get_mut(entity).unwrap().set_rotation_z(angle);
+                // Lasers keep facing the same way as the block they sit on moves
+                if let Some(parent) = parents.get_mut(entity) {
+                    let parent_rotation = transforms
+                        .get(parent.entity)
+                        .map_or(Quaternion::one(), |parent| parent.rotation);
+                    parent.local.rotation =
+                        parent_rotation.invert() * Quaternion::from_angle_z(Rad(angle));
+                }
+            }
 
+            let start_pos = Laser::muzzle(position, angle);
+            let aimed = (offset - turn).abs() <= Laser::AIM_TOLERANCE;
+            // Lasers without enough power are weaker, and lasers without any power can't fire
+            let power = if aimed
+                && raycaster.raycast(Vec::with_capacity(0), start_pos, target_pos) == Some(target)
+            {
+                consumer.map_or(1.0, PowerConsumer::draw)
+            } else {
+                0.0
+            };
+
+            if power > 0.0 {
+                lines
+                    .insert(
+                        entity,
+                        Line {
+                            pt: start_pos,
+                            pt2: target_pos,
+                            color: Vector3::new(1.0, 0.0, 0.0),
+                        },
+                    )
+                    .expect("Unable to set line component for laser!");
+
+                laser.charge += power;
+                if laser.charge >= 1.0 {
+                    laser.charge -= 1.0;
                     if let Some(health) = healths.get_mut(target) {
                         health.damage(1);
                     }
-
-                    continue;
                 }
+            } else {
+                lines.remove(entity);
             }
+        }
+    }
+}
+
+fn setup_door(builder: LazyBuilder) -> LazyBuilder {
+    builder.with(Door::default())
+}
+
+/// A block that opens up for crew members walking through it, and seals its room while it is closed
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct Door {
+    /// How far open the door is, from 0 (closed) to 1 (open)
+    openness: f32,
+    /// Locked doors stay closed, and crew members can't walk through them
+    pub locked: bool,
+}
+
+impl Door {
+    /// The share of the way that the door opens (or closes) every second
+    const SPEED: f32 = 3.0;
+    /// Doors open for crew members within this many tiles of them
+    const SENSE_RANGE: f32 = 1.5;
+    /// Open doors keep a sliver of their hitbox, since hitboxes can't be empty
+    const MIN_HITBOX_HEIGHT: f32 = 0.01;
 
-            lines.remove(entity);
+    pub fn is_closed(&self) -> bool {
+        self.openness <= 0.0
+    }
+
+    pub fn is_passable(&self) -> bool {
+        !self.locked
+    }
+
+    /// The part of the door's hitbox that is still above the floor
+    fn hitbox(&self, block: &Block) -> Hitbox {
+        match block.hitbox.shape {
+            ColliderShape::Cuboid(size) => {
+                let height = (size.z * (1.0 - self.openness)).max(Self::MIN_HITBOX_HEIGHT);
+                Hitbox::new(
+                    ColliderShape::Cuboid(Vector3::new(size.x, size.y, height)),
+                    block.hitbox.offset + Vector3::new(0.0, 0.0, (size.z - height) / 2.0),
+                )
+            }
+            ColliderShape::Sphere(_) => block.hitbox.clone(),
+        }
+    }
+}
+
+/// Opens doors for nearby crew members, and locks or unlocks the doors that the player clicks on.
+/// Open doors sink into the floor, along with their hitbox.
+#[derive(Default)]
+pub struct DoorSystem {
+    /// The block that was being clicked on during the last update, so that
+    /// holding the mouse button down only toggles the lock once
+    clicked: Option<Entity>,
+}
+
+impl<'a> System<'a> for DoorSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, InputManager>,
+        ReadExpect<'a, Blocks>,
+        WriteStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Crew>,
+        ReadStorage<'a, Construction>,
+        WriteStorage<'a, Door>,
+        WriteStorage<'a, Parent>,
+        WriteStorage<'a, Collider>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            input,
+            blocks,
+            mut ships,
+            block_entities,
+            crews,
+            constructions,
+            mut doors,
+            mut parents,
+            mut colliders,
+        ) = data;
+        let dt = 1.0 / 60.0;
+
+        let clicked = input.target.filter(|_| input.action == InputAction::Doors);
+        let toggled = clicked.filter(|target| Some(*target) != self.clicked);
+        self.clicked = clicked;
+
+        // Where every crew member is standing on their ship
+        let crew_positions: Vec<(Entity, Vector2<f32>)> = (&crews, &parents)
+            .join()
+            .map(|(crew, parent)| (parent.entity, crew.position))
+            .collect();
+
+        let mut changed_ships = Vec::new();
+        for (entity, door, block_entity, _) in
+            (&entities, &mut doors, &block_entities, !&constructions).join()
+        {
+            let ship = match parents.get(entity) {
+                Some(parent) => parent.entity,
+                None => continue,
+            };
+            let block = blocks.get_block(block_entity.block_id());
+            let was_closed = door.is_closed();
+            let was_locked = door.locked;
+            if toggled == Some(entity) {
+                door.locked = !door.locked;
+            }
+
+            let size = block_entity.orientation().rotate_size(block.size);
+            let center = ship::footprint_center(block_entity.root(), size);
+            let center = Vector2::new(center.x, center.y);
+            let crew_nearby = crew_positions.iter().any(|(crew_ship, position)| {
+                *crew_ship == ship && (position - center).magnitude() <= Door::SENSE_RANGE
+            });
+
+            let step = Door::SPEED * dt;
+            let openness = if crew_nearby && !door.locked {
+                (door.openness + step).min(1.0)
+            } else {
+                (door.openness - step).max(0.0)
+            };
+            if openness == door.openness && door.locked == was_locked {
+                continue;
+            }
+            door.openness = openness;
+
+            // Opening or closing a door joins or splits rooms, and locking it changes where crew can walk
+            if door.is_closed() != was_closed || door.locked != was_locked {
+                changed_ships.push(ship);
+            }
+            if let Some(parent) = parents.get_mut(entity) {
+                parent.local.position.z = -openness * block.height;
+            }
+            if let Some(collider) = colliders.get_mut(entity) {
+                collider.hitbox = door.hitbox(block);
+            }
+        }
+
+        for ship in changed_ships {
+            if let Some(ship) = ships.get_mut(ship) {
+                ship.layout_changed();
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn pick_target_only_checks_sight_until_it_finds_a_target() {
+        let mut world = World::new();
+        let (near, middle, far) = (
+            world.create_entity().build(),
+            world.create_entity().build(),
+            world.create_entity().build(),
+        );
+        let candidates = vec![
+            (far, 15.0, 3, GameItem::Iron),
+            (near, 5.0, 2, GameItem::Iron),
+            (middle, 10.0, 1, GameItem::Copper),
+        ];
+        let checked = RefCell::new(Vec::new());
+        let in_sight = |target| {
+            checked.borrow_mut().push(target);
+            target != near
+        };
+
+        let target = Laser::pick_target(TargetMode::Nearest, candidates, in_sight);
+
+        assert_eq!(target, Some(middle));
+        assert_eq!(*checked.borrow(), vec![near, middle]);
+    }
+
+    #[test]
+    fn pick_target_leaves_manual_lasers_alone() {
+        let mut world = World::new();
+        let asteroid = world.create_entity().build();
+        let candidates = vec![(asteroid, 5.0, 1, GameItem::Iron)];
+
+        let target = Laser::pick_target(TargetMode::Manual, candidates, |_| true);
+
+        assert_eq!(target, None);
+    }
+}
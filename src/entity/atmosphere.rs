@@ -0,0 +1,288 @@
+use super::{jobs::Construction, BlockEntity, DebugOverlay, Line, Ship, Transform};
+use crate::block::{Blocks, Door};
+use cgmath::{prelude::*, Point2, Vector3};
+use specs::{prelude::*, Component};
+use std::collections::{HashMap, HashSet};
+
+pub fn register_components(world: &mut World) {
+    world.register::<Atmosphere>();
+}
+
+pub fn setup_systems(builder: &mut DispatcherBuilder) {
+    builder.add(RoomSystem, "room_system", &["structure_system"]);
+    builder.add(RoomOverlaySystem, "room_overlay_system", &["room_system"]);
+}
+
+/// An enclosed area of floor on a ship
+pub struct Room {
+    pub tiles: Vec<Point2<i16>>,
+    /// The tiles next to the room that air escapes into, such as empty space or a floor without walls
+    pub breaches: Vec<Point2<i16>>,
+    /// How full of air the room is, from 0 (vacuum) to 1
+    pub oxygen: f32,
+}
+
+impl Room {
+    /// The share of a room's air lost every second through each breach,
+    /// so smaller rooms empty more quickly
+    const LEAK_RATE: f32 = 20.0;
+    /// How quickly life support fills a sealed room, per second
+    const REFILL_RATE: f32 = 0.05;
+
+    pub fn is_sealed(&self) -> bool {
+        self.breaches.is_empty()
+    }
+}
+
+/// The rooms of a ship, and the air inside of them
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct Atmosphere {
+    pub rooms: Vec<Room>,
+    /// The ship layout that the rooms were found in. Rooms found for the first time start out full of air.
+    layout_version: Option<u32>,
+}
+
+impl Atmosphere {
+    /// The room that the tile is in, if any
+    pub fn room_at(&self, pos: Point2<i16>) -> Option<&Room> {
+        self.rooms.iter().find(|room| room.tiles.contains(&pos))
+    }
+
+    /// How much air there is on the tile. Tiles outside of every room have none.
+    pub fn oxygen_at(&self, pos: Point2<i16>) -> f32 {
+        self.room_at(pos).map_or(0.0, |room| room.oxygen)
+    }
+}
+
+/// Finds the rooms of every ship with an atmosphere whenever the ship changes,
+/// then leaks air out of breached rooms and refills the sealed ones
+pub struct RoomSystem;
+
+impl<'a> System<'a> for RoomSystem {
+    type SystemData = (
+        ReadExpect<'a, Blocks>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Construction>,
+        ReadStorage<'a, Door>,
+        WriteStorage<'a, Atmosphere>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (blocks, ships, block_entities, constructions, doors, mut atmospheres) = data;
+        let dt = 1.0 / 60.0;
+        // Walls don't seal anything until they have been built, and doors only seal their room while closed
+        let is_airtight = |entity| {
+            !constructions.contains(entity)
+                && doors.get(entity).is_none_or(Door::is_closed)
+                && block_entities
+                    .get(entity)
+                    .is_some_and(|block| blocks.get_block(block.block_id()).is_airtight)
+        };
+
+        for (ship, atmosphere) in (&ships, &mut atmospheres).join() {
+            if atmosphere.layout_version != Some(ship.layout_version()) {
+                find_rooms(ship, atmosphere, is_airtight);
+            }
+
+            for room in &mut atmosphere.rooms {
+                room.oxygen = if room.is_sealed() {
+                    (room.oxygen + Room::REFILL_RATE * dt).min(1.0)
+                } else {
+                    let leak =
+                        Room::LEAK_RATE * room.breaches.len() as f32 / room.tiles.len() as f32;
+                    (room.oxygen - leak * dt).max(0.0)
+                };
+            }
+        }
+    }
+}
+
+/// Replaces the ship's rooms. The air already on each tile stays
+/// where it is, and spreads out evenly over the tile's new room.
+fn find_rooms(
+    ship: &Ship,
+    atmosphere: &mut Atmosphere,
+    is_airtight: impl Fn(Entity) -> bool + Copy,
+) {
+    let old_oxygen: HashMap<Point2<i16>, f32> = atmosphere
+        .rooms
+        .iter()
+        .flat_map(|room| room.tiles.iter().map(move |pos| (*pos, room.oxygen)))
+        .collect();
+
+    atmosphere.rooms = ship
+        .rooms(is_airtight)
+        .into_iter()
+        .map(|tiles| {
+            let members: HashSet<Point2<i16>> = tiles.iter().copied().collect();
+            let mut breaches: Vec<Point2<i16>> = tiles
+                .iter()
+                .flat_map(|pos| neighbours(*pos))
+                .filter(|pos| !members.contains(pos))
+                .filter(|pos| {
+                    !ship
+                        .tile(*pos)
+                        .and_then(|tile| tile.block())
+                        .is_some_and(is_airtight)
+                })
+                .collect();
+            breaches.sort_by_key(|pos| (pos.x, pos.y));
+            breaches.dedup();
+
+            let oxygen = if atmosphere.layout_version.is_some() {
+                let air: f32 = tiles
+                    .iter()
+                    .map(|pos| old_oxygen.get(pos).copied().unwrap_or(0.0))
+                    .sum();
+                air / tiles.len() as f32
+            } else {
+                1.0
+            };
+
+            Room {
+                tiles,
+                breaches,
+                oxygen,
+            }
+        })
+        .collect();
+    atmosphere.layout_version = Some(ship.layout_version());
+}
+
+/// Outlines every room in the debug overlay, coloured from red (no air) to green (full of air).
+/// The edges that air is escaping through are drawn in white.
+pub struct RoomOverlaySystem;
+
+impl RoomOverlaySystem {
+    /// Lines are drawn just above the floor, so that they aren't hidden inside of it
+    const HEIGHT: f32 = 0.1;
+}
+
+impl<'a> System<'a> for RoomOverlaySystem {
+    type SystemData = (
+        WriteExpect<'a, DebugOverlay>,
+        ReadStorage<'a, Atmosphere>,
+        ReadStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut overlay, atmospheres, transforms) = data;
+        if !overlay.show_rooms {
+            return;
+        }
+
+        for (atmosphere, transform) in (&atmospheres, &transforms).join() {
+            for room in &atmosphere.rooms {
+                let members: HashSet<Point2<i16>> = room.tiles.iter().copied().collect();
+                let room_color = Vector3::new(1.0 - room.oxygen, room.oxygen, 0.0);
+
+                for pos in &room.tiles {
+                    for next in neighbours(*pos) {
+                        if members.contains(&next) {
+                            continue;
+                        }
+
+                        let color = if room.breaches.contains(&next) {
+                            Vector3::new(1.0, 1.0, 1.0)
+                        } else {
+                            room_color
+                        };
+                        let (start, end) = edge(*pos, next);
+                        overlay.lines.push(Line {
+                            pt: transform.position + transform.rotation.rotate_vector(start),
+                            pt2: transform.position + transform.rotation.rotate_vector(end),
+                            color,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The positions of the tiles directly next to pos
+fn neighbours(pos: Point2<i16>) -> Vec<Point2<i16>> {
+    [(1, 0), (-1, 0), (0, 1), (0, -1)]
+        .iter()
+        .filter_map(|(x, y)| Some(Point2::new(pos.x.checked_add(*x)?, pos.y.checked_add(*y)?)))
+        .collect()
+}
+
+/// The two ends of the edge shared by neighbouring tiles, in the ship's own space
+fn edge(pos: Point2<i16>, next: Point2<i16>) -> (Vector3<f32>, Vector3<f32>) {
+    let center = Vector3::new(pos.x as f32, pos.y as f32, RoomOverlaySystem::HEIGHT);
+    let normal = Vector3::new((next.x - pos.x) as f32, (next.y - pos.y) as f32, 0.0) * 0.5;
+    let along = Vector3::new(-normal.y, normal.x, 0.0);
+
+    (center + normal - along, center + normal + along)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ship::tests::ship_from_layout;
+    use super::*;
+
+    #[test]
+    fn find_rooms_splits_floors_at_airtight_blocks() {
+        let mut world = World::new();
+        let ship = ship_from_layout(&mut world, &["#####", "#.D.#", "#####"]);
+        let mut atmosphere = Atmosphere::default();
+
+        find_rooms(&ship, &mut atmosphere, |_| true);
+
+        let tiles: Vec<_> = atmosphere.rooms.iter().map(|room| &room.tiles).collect();
+        assert_eq!(
+            tiles,
+            vec![&vec![Point2::new(1, 1)], &vec![Point2::new(3, 1)]]
+        );
+        assert!(atmosphere.rooms.iter().all(Room::is_sealed));
+        assert!(atmosphere.rooms.iter().all(|room| room.oxygen == 1.0));
+    }
+
+    #[test]
+    fn find_rooms_joins_rooms_through_blocks_that_let_air_through() {
+        let mut world = World::new();
+        let ship = ship_from_layout(&mut world, &["#####", "#.D.#", "#####"]);
+        let door = ship.tile(Point2::new(2, 1)).unwrap().block().unwrap();
+        let mut atmosphere = Atmosphere::default();
+
+        find_rooms(&ship, &mut atmosphere, |entity| entity != door);
+
+        assert_eq!(atmosphere.rooms.len(), 1);
+        assert_eq!(atmosphere.rooms[0].tiles.len(), 3);
+        assert!(atmosphere.rooms[0].is_sealed());
+    }
+
+    #[test]
+    fn find_rooms_finds_breaches_next_to_the_room() {
+        let mut world = World::new();
+        let ship = ship_from_layout(&mut world, &["###", "#..", "###"]);
+        let mut atmosphere = Atmosphere::default();
+
+        find_rooms(&ship, &mut atmosphere, |_| true);
+
+        assert_eq!(atmosphere.rooms.len(), 1);
+        assert_eq!(atmosphere.rooms[0].breaches, vec![Point2::new(3, 1)]);
+    }
+
+    #[test]
+    fn find_rooms_spreads_the_air_over_joined_rooms() {
+        let mut world = World::new();
+        let ship = ship_from_layout(&mut world, &["#####", "#.D.#", "#####"]);
+        let door = ship.tile(Point2::new(2, 1)).unwrap().block().unwrap();
+        let mut atmosphere = Atmosphere::default();
+        find_rooms(&ship, &mut atmosphere, |_| true);
+        for room in &mut atmosphere.rooms {
+            if room.tiles.contains(&Point2::new(1, 1)) {
+                room.oxygen = 0.0;
+            }
+        }
+
+        find_rooms(&ship, &mut atmosphere, |entity| entity != door);
+
+        // The door's own tile wasn't in a room, so it starts out without air
+        assert!((atmosphere.oxygen_at(Point2::new(2, 1)) - 1.0 / 3.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,407 @@
+use super::{
+    ship::{self, BuildAction, Orientation},
+    BlockEntity, FloorEntity, Ship,
+};
+use crate::block::Blocks;
+use crate::floor::Floors;
+use cgmath::{Point2, Vector2};
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use std::fmt;
+use std::path::Path;
+
+/// The version written into new blueprint files
+pub const BLUEPRINT_VERSION: u32 = 1;
+/// The layout of the ship that the player starts with
+pub const DEFAULT_BLUEPRINT_PATH: &str = "assets/blueprints/default.ron";
+
+/// The layout of a ship, which can be stamped into any ship at an offset.
+/// Blueprints are also used to store the player's ship inside of save files.
+#[derive(Serialize, Deserialize)]
+pub struct Blueprint {
+    pub blocks: Vec<BlueprintBlock>,
+    pub gadgets: Vec<BlueprintBlock>,
+    pub floors: Vec<BlueprintFloor>,
+}
+
+/// Blocks are stored by name so that blueprints survive changes to block IDs
+#[derive(Serialize, Deserialize)]
+pub struct BlueprintBlock {
+    pub block: String,
+    pub pos: Point2<i16>,
+    pub orientation: Orientation,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BlueprintFloor {
+    pub floor: String,
+    pub pos: Point2<i16>,
+}
+
+/// A blueprint as it is written to a standalone file
+#[derive(Serialize, Deserialize)]
+struct BlueprintFile {
+    version: u32,
+    blueprint: Blueprint,
+}
+
+#[derive(Debug)]
+pub enum BlueprintError {
+    Io(std::io::Error),
+    Format(ron::Error),
+    UnsupportedVersion(u32),
+    /// Every problem that stopped the blueprint from being stamped into a ship
+    Invalid(Vec<BlueprintProblem>),
+    NoShip,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlueprintProblem {
+    UnknownBlock(String),
+    UnknownFloor(String),
+    /// The tile lands outside of the ship's limits
+    OutOfBounds(Point2<i16>),
+}
+
+impl fmt::Display for BlueprintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlueprintError::Io(err) => write!(f, "Unable to access blueprint file: {}", err),
+            BlueprintError::Format(err) => write!(f, "Invalid blueprint file: {}", err),
+            BlueprintError::UnsupportedVersion(version) => write!(
+                f,
+                "Blueprint version {} is not supported (current version is {})",
+                version, BLUEPRINT_VERSION
+            ),
+            BlueprintError::Invalid(problems) => {
+                write!(f, "Invalid blueprint")?;
+                for (i, problem) in problems.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { ":" } else { ";" }, problem)?;
+                }
+                Ok(())
+            }
+            BlueprintError::NoShip => write!(f, "The player has no ship"),
+        }
+    }
+}
+
+impl fmt::Display for BlueprintProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlueprintProblem::UnknownBlock(name) => write!(f, "Unknown block: {}", name),
+            BlueprintProblem::UnknownFloor(name) => write!(f, "Unknown floor: {}", name),
+            BlueprintProblem::OutOfBounds(pos) => {
+                write!(
+                    f,
+                    "Tile ({}, {}) is outside of the ship's limits",
+                    pos.x, pos.y
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for BlueprintError {}
+
+impl From<std::io::Error> for BlueprintError {
+    fn from(err: std::io::Error) -> Self {
+        BlueprintError::Io(err)
+    }
+}
+
+impl From<ron::Error> for BlueprintError {
+    fn from(err: ron::Error) -> Self {
+        BlueprintError::Format(err)
+    }
+}
+
+impl Blueprint {
+    /// Copies the layout of a ship
+    pub fn from_ship(world: &World, ship: &Ship) -> Blueprint {
+        let blocks = world.fetch::<Blocks>();
+        let block_entities = world.read_component::<BlockEntity>();
+        let floor_entities = world.read_component::<FloorEntity>();
+        let mut blueprint = Blueprint {
+            blocks: Vec::new(),
+            gadgets: Vec::new(),
+            floors: Vec::new(),
+        };
+
+        // Multiblocks are only stored at their root tile
+        let blueprint_block = |entity: Option<Entity>, pos: &Point2<i16>| {
+            entity
+                .and_then(|entity| block_entities.get(entity))
+                .filter(|block| block.root() == *pos)
+                .map(|block| BlueprintBlock {
+                    block: blocks.get_block(block.block_id()).type_name.clone(),
+                    pos: *pos,
+                    orientation: block.orientation(),
+                })
+        };
+
+        for (pos, tile) in ship.tiles() {
+            blueprint.blocks.extend(blueprint_block(tile.block(), pos));
+            blueprint
+                .gadgets
+                .extend(blueprint_block(tile.gadget(), pos));
+            blueprint.floors.extend(
+                tile.floor()
+                    .and_then(|entity| floor_entities.get(entity))
+                    .map(|floor| BlueprintFloor {
+                        floor: floor.0.name.to_string(),
+                        pos: *pos,
+                    }),
+            );
+        }
+
+        // Tiles are stored in a HashMap, so sort them to keep files stable
+        blueprint
+            .blocks
+            .sort_by_key(|block| (block.pos.x, block.pos.y));
+        blueprint
+            .gadgets
+            .sort_by_key(|block| (block.pos.x, block.pos.y));
+        blueprint
+            .floors
+            .sort_by_key(|floor| (floor.pos.x, floor.pos.y));
+
+        blueprint
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Blueprint, BlueprintError> {
+        let text = std::fs::read_to_string(path)?;
+        let file: BlueprintFile = ron::de::from_str(&text)?;
+
+        match file.version {
+            BLUEPRINT_VERSION => Ok(file.blueprint),
+            version => Err(BlueprintError::UnsupportedVersion(version)),
+        }
+    }
+
+    pub fn save(self, path: impl AsRef<Path>) -> Result<(), BlueprintError> {
+        let path = path.as_ref();
+        let file = BlueprintFile {
+            version: BLUEPRINT_VERSION,
+            blueprint: self,
+        };
+        // Keep each block on a single line, so that blueprints are easy to read and edit
+        let config = ron::ser::PrettyConfig::new().with_depth_limit(3);
+        let text = ron::ser::to_string_pretty(&file, config)?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, text)?;
+
+        Ok(())
+    }
+
+    /// Converts the blueprint into the build actions that stamp it into the ship,
+    /// with every tile moved by the offset. Blocks and floors come first, so that
+    /// gadgets have something to sit on.
+    ///
+    /// Unknown names and tiles outside of the ship's limits are reported before anything is built.
+    pub fn build_actions(
+        &self,
+        world: &World,
+        ship: &Ship,
+        offset: Vector2<i16>,
+    ) -> Result<Vec<BuildAction>, BlueprintError> {
+        let blocks = world.fetch::<Blocks>();
+        let floors = world.fetch::<Floors>();
+        let mut problems = Vec::new();
+        let check_bounds = |tiles: Vec<Point2<i16>>, problems: &mut Vec<BlueprintProblem>| {
+            for pos in tiles {
+                if !ship.in_bounds(pos) {
+                    problems.push(BlueprintProblem::OutOfBounds(pos));
+                }
+            }
+        };
+        // Moves a tile by the offset. Tiles that would land past the largest tile position
+        // are reported as out of bounds, at the edge that they went past.
+        let shift = |pos: Point2<i16>,
+                     offset: Vector2<i16>,
+                     problems: &mut Vec<BlueprintProblem>| {
+            match (pos.x.checked_add(offset.x), pos.y.checked_add(offset.y)) {
+                (Some(x), Some(y)) => Some(Point2::new(x, y)),
+                _ => {
+                    problems.push(BlueprintProblem::OutOfBounds(Point2::new(
+                        pos.x.saturating_add(offset.x),
+                        pos.y.saturating_add(offset.y),
+                    )));
+                    None
+                }
+            }
+        };
+        let block_actions = |list: &[BlueprintBlock], problems: &mut Vec<BlueprintProblem>| {
+            let mut actions = Vec::new();
+            for block in list {
+                let id = match blocks.find_block(&block.block) {
+                    Some(id) => id,
+                    None => {
+                        problems.push(BlueprintProblem::UnknownBlock(block.block.clone()));
+                        continue;
+                    }
+                };
+                let pos = match shift(block.pos, offset, problems) {
+                    Some(pos) => pos,
+                    None => continue,
+                };
+                let size = block.orientation.rotate_size(blocks.get_block(id).size);
+                match ship::footprint(pos, size) {
+                    Some(tiles) => check_bounds(tiles, problems),
+                    None => {
+                        let far_corner = Vector2::new(size.x as i16 - 1, size.y as i16 - 1);
+                        shift(pos, far_corner, problems);
+                    }
+                }
+                actions.push(BuildAction::BuildBlock(pos, id, block.orientation));
+            }
+            actions
+        };
+
+        let mut actions = block_actions(&self.blocks, &mut problems);
+        for floor in &self.floors {
+            let found = match floors.get_floor(&floor.floor) {
+                Some(found) => found,
+                None => {
+                    problems.push(BlueprintProblem::UnknownFloor(floor.floor.clone()));
+                    continue;
+                }
+            };
+            if let Some(pos) = shift(floor.pos, offset, &mut problems) {
+                check_bounds(vec![pos], &mut problems);
+                actions.push(BuildAction::BuildFloor(pos, found));
+            }
+        }
+        actions.extend(block_actions(&self.gadgets, &mut problems));
+
+        if problems.is_empty() {
+            Ok(actions)
+        } else {
+            Err(BlueprintError::Invalid(problems))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::load_blocks;
+    use crate::floor::load_floors;
+    use crate::graphics::MeshManager;
+    use ship::TileBounds;
+
+    fn world() -> World {
+        let mut meshes = MeshManager::new();
+        let mut world = World::new();
+        world.insert(load_blocks(&mut meshes));
+        world.insert(load_floors(&mut meshes));
+        world
+    }
+
+    fn block(name: &str, x: i16, y: i16) -> BlueprintBlock {
+        BlueprintBlock {
+            block: name.to_string(),
+            pos: Point2::new(x, y),
+            orientation: Orientation::East,
+        }
+    }
+
+    fn floor(name: &str, x: i16, y: i16) -> BlueprintFloor {
+        BlueprintFloor {
+            floor: name.to_string(),
+            pos: Point2::new(x, y),
+        }
+    }
+
+    /// The name of what each action builds, and where
+    fn describe(world: &World, actions: &[BuildAction]) -> Vec<(String, Point2<i16>)> {
+        let blocks = world.fetch::<Blocks>();
+        actions
+            .iter()
+            .map(|action| match action {
+                BuildAction::BuildBlock(pos, id, _) => {
+                    (blocks.get_block(*id).type_name.clone(), *pos)
+                }
+                BuildAction::BuildFloor(pos, floor) => (floor.name.to_string(), *pos),
+                _ => panic!("Blueprints only build things"),
+            })
+            .collect()
+    }
+
+    fn problems(result: Result<Vec<BuildAction>, BlueprintError>) -> Vec<BlueprintProblem> {
+        match result {
+            Err(BlueprintError::Invalid(problems)) => problems,
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("The blueprint should have been rejected"),
+        }
+    }
+
+    #[test]
+    fn build_actions_moves_every_tile_by_the_offset() {
+        let world = world();
+        let blueprint = Blueprint {
+            blocks: vec![block("wall", 0, 0)],
+            gadgets: vec![block("Laser", 0, 0)],
+            floors: vec![floor("metal", 1, 0)],
+        };
+
+        let actions = blueprint
+            .build_actions(&world, &Ship::empty(), Vector2::new(2, 3))
+            .unwrap();
+
+        assert_eq!(
+            describe(&world, &actions),
+            vec![
+                ("wall".to_string(), Point2::new(2, 3)),
+                ("metal".to_string(), Point2::new(3, 3)),
+                ("Laser".to_string(), Point2::new(2, 3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn build_actions_reports_every_problem() {
+        let world = world();
+        let mut ship = Ship::empty();
+        ship.set_limits(Some(TileBounds::new(Point2::new(0, 0), Point2::new(3, 3))));
+        let blueprint = Blueprint {
+            blocks: vec![block("Teleporter", 0, 0), block("Cargo Bay", 3, 0)],
+            gadgets: Vec::new(),
+            floors: vec![floor("gravel", 0, 0), floor("metal", 0, 4)],
+        };
+
+        assert_eq!(
+            problems(blueprint.build_actions(&world, &ship, Vector2::new(0, 0))),
+            vec![
+                BlueprintProblem::UnknownBlock("Teleporter".to_string()),
+                BlueprintProblem::OutOfBounds(Point2::new(4, 0)),
+                BlueprintProblem::OutOfBounds(Point2::new(4, 1)),
+                BlueprintProblem::UnknownFloor("gravel".to_string()),
+                BlueprintProblem::OutOfBounds(Point2::new(0, 4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn build_actions_reports_tiles_past_the_largest_position() {
+        let world = world();
+        let blueprint = Blueprint {
+            blocks: vec![
+                block("wall", i16::MAX, 0),
+                block("Cargo Bay", 0, i16::MAX - 1),
+            ],
+            gadgets: Vec::new(),
+            floors: Vec::new(),
+        };
+
+        assert_eq!(
+            problems(blueprint.build_actions(&world, &Ship::empty(), Vector2::new(1, 1))),
+            vec![
+                BlueprintProblem::OutOfBounds(Point2::new(i16::MAX, 1)),
+                BlueprintProblem::OutOfBounds(Point2::new(2, i16::MAX)),
+            ]
+        );
+    }
+}
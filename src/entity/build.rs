@@ -0,0 +1,334 @@
+use super::{
+    input::{self, InputAction, InputManager},
+    jobs::ConstructionSites,
+    ship::{self, BuildAction, BuildError, Orientation, PlayerShip},
+    BlockEntity, Ship, Transform, WindowSize,
+};
+use crate::block::{BlockId, Blocks};
+use crate::floor::Floor;
+use crate::graphics::{Camera, MeshId, MeshManager, ModelId};
+use crate::item::Inventory;
+use cgmath::{Matrix4, Point2, Point3, Rad, Vector3};
+use specs::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use winit::event::VirtualKeyCode;
+
+/// The thing that will be built when the player clicks on a tile
+#[derive(Clone, Copy)]
+pub enum BuildSelection {
+    Block(BlockId),
+    Floor(Floor),
+}
+
+/// The state of the player's build mode, which is active while the
+/// current input action is `InputAction::Build`
+#[derive(Default)]
+pub struct BuildMode {
+    pub selection: Option<BuildSelection>,
+    /// The direction that new blocks will face. Turned with the R key.
+    pub orientation: Orientation,
+    /// The tile currently under the mouse cursor
+    pub hovered: Option<Point2<i16>>,
+    /// Build actions made by the player, executed at the end of the update
+    pending: Vec<BuildAction>,
+    /// The model used to preview the selection on the hovered tile
+    ghost: Option<(MeshId, ModelId)>,
+    /// Translucent copies of each mesh, tinted by whether placement is valid
+    ghost_meshes: HashMap<(MeshId, bool), MeshId>,
+    left_mb: bool,
+    right_mb: bool,
+    rotate_key: bool,
+}
+
+impl BuildMode {
+    pub fn take_pending(&mut self) -> Vec<BuildAction> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// The inverse of one batch of build actions, which undoes (or redoes) it
+#[derive(Default)]
+pub struct BuildChange {
+    pub actions: Vec<BuildAction>,
+    /// The blocks rebuilt by the actions that were still construction sites when they were
+    /// removed, so that they go back to how far along they were. Every other block that is
+    /// rebuilt comes back finished.
+    pub construction: ConstructionSites,
+    /// What each of the actions expects to remove from its tile, or None if it doesn't
+    /// remove anything
+    targets: Vec<Option<Entity>>,
+}
+
+impl BuildChange {
+    /// Remembers what each action would remove from the ship as it is now
+    pub fn new(actions: Vec<BuildAction>, construction: ConstructionSites, ship: &Ship) -> Self {
+        let targets = actions
+            .iter()
+            .map(|action| removal_target(ship, action))
+            .collect();
+
+        BuildChange {
+            actions,
+            construction,
+            targets,
+        }
+    }
+
+    /// Whether everything that the change removes is still on the ship. Blocks that were
+    /// destroyed or broke away since then may have been replaced by something else.
+    pub fn still_applies(&self, ship: &Ship) -> bool {
+        self.actions
+            .iter()
+            .zip(&self.targets)
+            .all(|(action, target)| target.is_none() || removal_target(ship, action) == *target)
+    }
+}
+
+/// The block, gadget, or floor that the action would remove from the ship
+fn removal_target(ship: &Ship, action: &BuildAction) -> Option<Entity> {
+    match action {
+        BuildAction::RemoveBlock(pos) => ship.tile(*pos)?.block(),
+        BuildAction::RemoveGadget(pos) => ship.tile(*pos)?.gadget(),
+        BuildAction::RemoveFloor(pos) => ship.tile(*pos)?.floor(),
+        BuildAction::BuildBlock(..) | BuildAction::BuildFloor(..) => None,
+    }
+}
+
+/// The changes that undo (and redo) the player's most recent changes to their ship
+#[derive(Default)]
+pub struct BuildHistory {
+    undo: VecDeque<BuildChange>,
+    redo: Vec<BuildChange>,
+}
+
+impl BuildHistory {
+    /// The number of changes that are remembered
+    const LIMIT: usize = 64;
+
+    /// Records a new change to the ship, which can no longer be followed by a redo
+    pub fn record(&mut self, inverse: BuildChange) {
+        self.redo.clear();
+        self.push_undo(inverse);
+    }
+
+    pub fn take_undo(&mut self) -> Option<BuildChange> {
+        self.undo.pop_back()
+    }
+
+    pub fn take_redo(&mut self) -> Option<BuildChange> {
+        self.redo.pop()
+    }
+
+    /// Records the inverse of an undo, so that it can be redone
+    pub fn push_redo(&mut self, inverse: BuildChange) {
+        if !inverse.actions.is_empty() {
+            self.redo.push(inverse);
+        }
+    }
+
+    /// Records the inverse of a redo (or a new change), so that it can be undone
+    pub fn push_undo(&mut self, inverse: BuildChange) {
+        if inverse.actions.is_empty() {
+            return;
+        }
+
+        self.undo.push_back(inverse);
+        if self.undo.len() > Self::LIMIT {
+            self.undo.pop_front();
+        }
+    }
+}
+
+pub struct BuildSystem;
+
+impl<'a> System<'a> for BuildSystem {
+    type SystemData = (
+        WriteExpect<'a, BuildMode>,
+        WriteExpect<'a, MeshManager>,
+        ReadExpect<'a, InputManager>,
+        ReadExpect<'a, Camera>,
+        ReadExpect<'a, WindowSize>,
+        ReadExpect<'a, Blocks>,
+        ReadExpect<'a, Inventory>,
+        ReadExpect<'a, PlayerShip>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut build_mode,
+            mut mesh_manager,
+            input,
+            camera,
+            window_size,
+            blocks,
+            inventory,
+            player_ship,
+            ships,
+            block_entities,
+            transforms,
+        ) = data;
+
+        // Clicks only count on the update that the button goes down
+        let left_clicked = input.left_mb && !build_mode.left_mb;
+        let right_clicked = input.right_mb && !build_mode.right_mb;
+        build_mode.left_mb = input.left_mb;
+        build_mode.right_mb = input.right_mb;
+
+        let rotate_key = input.keys.is_key_down(VirtualKeyCode::R);
+        if rotate_key && !build_mode.rotate_key && input.action == InputAction::Build {
+            build_mode.orientation = build_mode.orientation.rotated();
+        }
+        build_mode.rotate_key = rotate_key;
+
+        let ship = ships.get(player_ship.0);
+        let ship_transform = transforms
+            .get(player_ship.0)
+            .cloned()
+            .unwrap_or_else(|| Transform::from_position(0.0, 0.0, 0.0));
+        build_mode.hovered = match (&input.action, ship) {
+            (InputAction::Build, Some(_)) => {
+                // Tiles are found in the ship's own space, since the ship may have moved and turned
+                let (near, far) = input::mouse_ray(&input, &camera, &window_size);
+                hovered_tile(ship_transform.to_local(near), ship_transform.to_local(far))
+            }
+            _ => None,
+        };
+
+        // Work out what the ghost should look like, and where it should be
+        let mut ghost = None;
+        if let (Some(ship), Some(pos), Some(selection)) =
+            (ship, build_mode.hovered, build_mode.selection)
+        {
+            let orientation = build_mode.orientation;
+            let (mesh_id, result, model) = match selection {
+                BuildSelection::Block(block_id) => {
+                    let block = blocks.get_block(block_id);
+                    let center = ship::footprint_center(pos, orientation.rotate_size(block.size));
+                    let result = ship
+                        .check_block(
+                            &blocks,
+                            |entity| block_entities.get(entity).map(BlockEntity::block_id),
+                            pos,
+                            block_id,
+                            orientation,
+                        )
+                        .and_then(|height| {
+                            inventory
+                                .check_items(&block.cost)
+                                .map(|_| height)
+                                .map_err(BuildError::NotEnoughItems)
+                        });
+                    let height = *result.as_ref().unwrap_or(&0.0);
+
+                    (
+                        block.mesh_id,
+                        result.map(|_| BuildAction::BuildBlock(pos, block_id, orientation)),
+                        Matrix4::from_translation(Vector3::new(center.x, center.y, height))
+                            * Matrix4::from_angle_z(Rad(orientation.angle())),
+                    )
+                }
+                BuildSelection::Floor(floor) => (
+                    floor.into(),
+                    ship.check_floor(pos)
+                        .map(|_| BuildAction::BuildFloor(pos, floor)),
+                    Matrix4::from_translation(Vector3::new(pos.x as f32, pos.y as f32, 0.0)),
+                ),
+            };
+
+            let valid = result.is_ok();
+            let ghost_mesh = *build_mode
+                .ghost_meshes
+                .entry((mesh_id, valid))
+                .or_insert_with(|| {
+                    let mut mesh = mesh_manager.get_mesh(mesh_id).clone();
+                    mesh.name = format!("Ghost({}, valid={})", mesh.name, valid);
+                    mesh.tint(if valid {
+                        Point3::new(0.0, 1.0, 0.0)
+                    } else {
+                        Point3::new(1.0, 0.0, 0.0)
+                    });
+                    mesh_manager.add_translucent(&mesh)
+                });
+            ghost = Some((ghost_mesh, ship_transform.as_matrix() * model));
+
+            if left_clicked {
+                match result {
+                    Ok(action) => build_mode.pending.push(action),
+                    Err(err) => println!("[Build] Unable to build: {:?}", err),
+                }
+            }
+        }
+
+        // Right clicking removes the topmost thing on the tile
+        if let (Some(ship), Some(pos), true) = (ship, build_mode.hovered, right_clicked) {
+            let action = ship.tile(pos).and_then(|tile| {
+                if tile.gadget().is_some() {
+                    Some(BuildAction::RemoveGadget(pos))
+                } else if tile.block().is_some() {
+                    Some(BuildAction::RemoveBlock(pos))
+                } else if tile.floor().is_some() {
+                    Some(BuildAction::RemoveFloor(pos))
+                } else {
+                    None
+                }
+            });
+            build_mode.pending.extend(action);
+        }
+
+        match (build_mode.ghost, ghost) {
+            (Some((mesh_id, model_id)), Some((new_mesh_id, model))) if mesh_id == new_mesh_id => {
+                mesh_manager.update_model(mesh_id, model_id, model);
+            }
+            (old, new) => {
+                if let Some((mesh_id, model_id)) = old {
+                    mesh_manager.remove_model(mesh_id, model_id);
+                }
+                build_mode.ghost =
+                    new.map(|(mesh_id, model)| (mesh_id, mesh_manager.new_model(mesh_id, model)));
+            }
+        }
+    }
+}
+
+/// Finds the tile where the ray hits the ship's grid plane (z = 0)
+fn hovered_tile(near: Vector3<f32>, far: Vector3<f32>) -> Option<Point2<i16>> {
+    if (near.z - far.z).abs() < f32::EPSILON {
+        return None;
+    }
+
+    let t = near.z / (near.z - far.z);
+    if !(0.0..=1.0).contains(&t) {
+        return None;
+    }
+
+    let hit = near + (far - near) * t;
+    Some(Point2::new(hit.x.round() as i16, hit.y.round() as i16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ship::tests::ship_from_layout;
+    use super::*;
+
+    #[test]
+    fn changes_stop_applying_once_their_blocks_are_replaced() {
+        let mut world = World::new();
+        let ship = ship_from_layout(&mut world, &["#."]);
+        let change = BuildChange::new(
+            vec![
+                BuildAction::RemoveBlock(Point2::new(0, 0)),
+                BuildAction::RemoveFloor(Point2::new(1, 0)),
+            ],
+            ConstructionSites::new(),
+            &ship,
+        );
+        let rebuilt = ship_from_layout(&mut world, &["#."]);
+
+        assert!(change.still_applies(&ship));
+        assert!(!change.still_applies(&rebuilt));
+        assert!(!change.still_applies(&Ship::empty()));
+    }
+}
@@ -0,0 +1,375 @@
+use super::{
+    atmosphere::Atmosphere, hierarchy::Parent, jobs::Construction, objects::ObjectMeshes, Model,
+    Ship, Transform,
+};
+use crate::block::Door;
+use cgmath::{prelude::*, Point2, Vector2};
+use rand::{seq::IteratorRandom, Rng};
+use specs::{prelude::*, Component};
+use std::ops::Range;
+
+pub fn register_components(world: &mut World) {
+    world.register::<Crew>();
+}
+
+pub fn setup_systems(builder: &mut DispatcherBuilder) {
+    builder.add(CrewSystem, "crew_system", &["room_system"]);
+}
+
+/// What a crew member is busy with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrewState {
+    /// Standing around until they decide to go somewhere else
+    Idle,
+    /// Wandering over to another part of the ship
+    Walking,
+    /// Heading for a room that still has air in it
+    SeekingAir,
+    /// Walking over to a job, or doing it
+    Working,
+    /// Out of breath, and unable to move until the air comes back
+    Unconscious,
+}
+
+/// Someone who lives on a ship and walks around its floors.
+/// Crew members are attached to their ship with a `Parent`.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct Crew {
+    pub state: CrewState,
+    /// Where the crew member is standing, in ship tiles
+    pub position: Vector2<f32>,
+    /// The direction the crew member is facing, counter-clockwise from east in radians
+    facing: f32,
+    /// The tiles left to walk through, with the next one at the end
+    path: Vec<Point2<i16>>,
+    /// The ship layout that the path was found in
+    layout_version: u32,
+    /// How long to stand around for before wandering off, in seconds
+    idle_time: f32,
+    /// How long the crew member can go without air, from 0 (unconscious) to 1
+    pub breath: f32,
+    /// The block that the crew member has been given a job on
+    job: Option<Entity>,
+}
+
+impl Crew {
+    /// How many crew members a new ship starts with
+    pub const STARTING_CREW: usize = 3;
+    /// Tiles per second
+    const WALK_SPEED: f32 = 1.5;
+    /// Rooms with less oxygen than this can't be breathed in
+    const BREATHABLE_OXYGEN: f32 = 0.3;
+    /// How much breath is lost every second without air, and how much is regained with it
+    const SUFFOCATION_RATE: f32 = 0.1;
+    const RECOVERY_RATE: f32 = 0.5;
+    /// Unconscious crew members wake up once they have this much breath again
+    const WAKE_BREATH: f32 = 0.5;
+    const IDLE_TIME: Range<f32> = 2.0..6.0;
+
+    pub fn new(position: Vector2<f32>, breath: f32) -> Self {
+        Self {
+            state: CrewState::Idle,
+            position,
+            facing: 0.0,
+            path: Vec::new(),
+            layout_version: 0,
+            idle_time: 0.0,
+            breath,
+            job: None,
+        }
+    }
+
+    pub fn job(&self) -> Option<Entity> {
+        self.job
+    }
+
+    /// Whether the crew member is free to be given a job
+    pub fn is_available(&self) -> bool {
+        matches!(self.state, CrewState::Idle | CrewState::Walking)
+            && self.breath >= Self::WAKE_BREATH
+    }
+
+    /// Whether the crew member has reached their job, and is working on it
+    pub fn is_working(&self) -> bool {
+        self.state == CrewState::Working && self.path.is_empty()
+    }
+
+    /// Sends the crew member along the path to work on a block
+    pub fn assign_job(&mut self, target: Entity, path: Vec<Point2<i16>>) {
+        self.job = Some(target);
+        self.path = reversed(path);
+        self.state = CrewState::Working;
+    }
+
+    /// Lets the crew member go back to what they were doing before they were given a job
+    pub fn finish_job(&mut self) {
+        self.job = None;
+        if self.state == CrewState::Working {
+            self.path.clear();
+            self.state = CrewState::Idle;
+        }
+    }
+
+    /// The tile that the crew member is standing on
+    pub fn tile(&self) -> Point2<i16> {
+        Point2::new(
+            self.position.x.round() as i16,
+            self.position.y.round() as i16,
+        )
+    }
+
+    /// Where the crew member stands relative to their ship
+    fn local_transform(&self) -> Transform {
+        let mut transform = Transform::from_position(self.position.x, self.position.y, 0.0);
+        transform.set_rotation_z(self.facing);
+        transform
+    }
+
+    /// Decides what to do next, and finds the path to get there
+    fn think(
+        &mut self,
+        ship: &Ship,
+        atmosphere: Option<&Atmosphere>,
+        breathable: bool,
+        is_passable: impl Fn(Entity) -> bool + Copy,
+        rng: &mut impl Rng,
+    ) {
+        let dt = 1.0 / 60.0;
+
+        if self.breath <= 0.0 {
+            self.state = CrewState::Unconscious;
+            self.path.clear();
+            self.job = None;
+            return;
+        }
+
+        // Walls may have been built in the way (or knocked down), so the path is found again
+        if self.layout_version != ship.layout_version() {
+            self.layout_version = ship.layout_version();
+            if let Some(goal) = self.path.first() {
+                match ship.find_path(self.tile(), *goal, is_passable) {
+                    Some(path) => self.path = reversed(path),
+                    None => {
+                        self.path.clear();
+                        // Crew members can't get to jobs that have been walled off
+                        self.finish_job();
+                    }
+                }
+            }
+        }
+
+        match self.state {
+            CrewState::Unconscious => {
+                if self.breath >= Self::WAKE_BREATH {
+                    self.state = CrewState::Idle;
+                }
+            }
+            CrewState::Idle => {
+                self.idle_time -= dt;
+                // Running out of air is noticed straight away
+                if self.idle_time > 0.0 && breathable {
+                    return;
+                }
+
+                self.idle_time = rng.gen_range(Self::IDLE_TIME);
+                let (state, path) = if breathable {
+                    (
+                        CrewState::Walking,
+                        self.wander(ship, atmosphere, is_passable, rng),
+                    )
+                } else {
+                    (
+                        CrewState::SeekingAir,
+                        self.find_air(ship, atmosphere, is_passable),
+                    )
+                };
+                if let Some(path) = path {
+                    self.path = reversed(path);
+                    self.state = state;
+                }
+            }
+            CrewState::Walking if !breathable => {
+                self.path.clear();
+                self.state = CrewState::Idle;
+                self.idle_time = 0.0;
+            }
+            // Crew members hold their breath to work, such as when fixing a breach
+            CrewState::Working if self.breath < Self::WAKE_BREATH || self.job.is_none() => {
+                self.finish_job();
+                self.idle_time = 0.0;
+            }
+            CrewState::Working => {}
+            CrewState::Walking | CrewState::SeekingAir => {
+                if self.path.is_empty() {
+                    self.state = CrewState::Idle;
+                }
+            }
+        }
+    }
+
+    /// A path to a random tile that can be breathed on, or anywhere on the ship if there is no air
+    fn wander(
+        &self,
+        ship: &Ship,
+        atmosphere: Option<&Atmosphere>,
+        is_passable: impl Fn(Entity) -> bool + Copy,
+        rng: &mut impl Rng,
+    ) -> Option<Vec<Point2<i16>>> {
+        let goal = breathable_rooms(atmosphere)
+            .flat_map(|tiles| tiles.iter().copied())
+            .filter(|pos| ship.is_walkable(*pos, is_passable))
+            .choose(rng)
+            .or_else(|| {
+                ship.tiles()
+                    .map(|(pos, _)| *pos)
+                    .filter(|pos| ship.is_walkable(*pos, is_passable))
+                    .choose(rng)
+            })?;
+
+        ship.find_path(self.tile(), goal, is_passable)
+    }
+
+    /// The shortest path into any room that can be breathed in
+    fn find_air(
+        &self,
+        ship: &Ship,
+        atmosphere: Option<&Atmosphere>,
+        is_passable: impl Fn(Entity) -> bool + Copy,
+    ) -> Option<Vec<Point2<i16>>> {
+        let start = self.tile();
+        let distance = |pos: &Point2<i16>| {
+            (i32::from(pos.x) - i32::from(start.x)).abs()
+                + (i32::from(pos.y) - i32::from(start.y)).abs()
+        };
+
+        breathable_rooms(atmosphere)
+            .filter_map(|tiles| {
+                let goal = tiles
+                    .iter()
+                    .filter(|pos| ship.is_walkable(**pos, is_passable))
+                    .min_by_key(|pos| distance(pos))?;
+                ship.find_path(start, *goal, is_passable)
+            })
+            .min_by_key(Vec::len)
+    }
+
+    /// Moves towards the next tile on the path
+    fn walk(&mut self) {
+        let dt = 1.0 / 60.0;
+        if self.state == CrewState::Unconscious {
+            return;
+        }
+        let next = match self.path.last() {
+            Some(next) => Vector2::new(f32::from(next.x), f32::from(next.y)),
+            None => return,
+        };
+
+        let offset = next - self.position;
+        let step = Self::WALK_SPEED * dt;
+        if offset.magnitude() <= step {
+            self.position = next;
+            self.path.pop();
+        } else {
+            self.position += offset.normalize() * step;
+        }
+        if offset != Vector2::zero() {
+            self.facing = offset.y.atan2(offset.x);
+        }
+    }
+}
+
+/// The tiles of every room with enough air to breathe
+fn breathable_rooms(atmosphere: Option<&Atmosphere>) -> impl Iterator<Item = &Vec<Point2<i16>>> {
+    atmosphere
+        .into_iter()
+        .flat_map(|atmosphere| &atmosphere.rooms)
+        .filter(|room| room.oxygen >= Crew::BREATHABLE_OXYGEN)
+        .map(|room| &room.tiles)
+}
+
+/// Paths are stored back to front, so that the next tile can be popped off the end
+fn reversed(mut path: Vec<Point2<i16>>) -> Vec<Point2<i16>> {
+    path.reverse();
+    path
+}
+
+/// Puts crew members on random floors of a ship that has already been built
+pub fn spawn_crew(world: &mut World, ship: Entity, count: usize) {
+    let tiles = world.read_component::<Ship>().get(ship).map(|ship| {
+        ship.tiles()
+            .map(|(pos, _)| *pos)
+            .filter(|pos| ship.is_walkable(*pos, |_| false))
+            .choose_multiple(&mut rand::thread_rng(), count)
+    });
+
+    for pos in tiles.unwrap_or_default() {
+        let position = Vector2::new(f32::from(pos.x), f32::from(pos.y));
+        add_crew(world, ship, Crew::new(position, 1.0));
+    }
+}
+
+/// Adds a crew member to a ship
+pub fn add_crew(world: &mut World, ship: Entity, crew: Crew) -> Entity {
+    let local = crew.local_transform();
+    let transform = world
+        .read_component::<Transform>()
+        .get(ship)
+        .map_or_else(|| local.clone(), |transform| transform.apply_to(&local));
+    let mesh = world.fetch::<ObjectMeshes>().crew;
+
+    world
+        .create_entity()
+        .with(Model::new(mesh))
+        .with(transform)
+        .with(Parent {
+            entity: ship,
+            local,
+        })
+        .with(crew)
+        .build()
+}
+
+/// Walks every crew member around their ship, and makes them breathe the air of the room they are in
+pub struct CrewSystem;
+
+impl<'a> System<'a> for CrewSystem {
+    type SystemData = (
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, Atmosphere>,
+        WriteStorage<'a, Crew>,
+        WriteStorage<'a, Parent>,
+        ReadStorage<'a, Door>,
+        ReadStorage<'a, Construction>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (ships, atmospheres, mut crews, mut parents, doors, constructions) = data;
+        let dt = 1.0 / 60.0;
+        let mut rng = rand::thread_rng();
+        // Doors open up for crew members on their way through, unless they are locked
+        let is_passable = |entity| {
+            doors.get(entity).is_some_and(Door::is_passable) && !constructions.contains(entity)
+        };
+
+        for (crew, parent) in (&mut crews, &mut parents).join() {
+            let ship = match ships.get(parent.entity) {
+                Some(ship) => ship,
+                None => continue,
+            };
+            let atmosphere = atmospheres.get(parent.entity);
+
+            let oxygen = atmosphere.map_or(0.0, |atmosphere| atmosphere.oxygen_at(crew.tile()));
+            let breathable = oxygen >= Crew::BREATHABLE_OXYGEN;
+            crew.breath = if breathable {
+                (crew.breath + Crew::RECOVERY_RATE * dt).min(1.0)
+            } else {
+                (crew.breath - Crew::SUFFOCATION_RATE * dt).max(0.0)
+            };
+
+            crew.think(ship, atmosphere, breathable, is_passable, &mut rng);
+            crew.walk();
+            parent.local = crew.local_transform();
+        }
+    }
+}
@@ -1,7 +1,6 @@
 use super::{
-    objects::{Asteroid, Health, ObjectMeshes},
-    physics::{Collider, ColliderShape, Hitbox, RigidBody},
-    Model, ToBeRemoved, Transform,
+    objects::{self, Asteroid, ObjectMeshes},
+    ToBeRemoved, Transform,
 };
 use crate::item::GameItem;
 use cgmath::Vector3;
@@ -30,11 +29,11 @@ pub fn init_world(world: &mut World) {
 
 #[derive(Component)]
 #[storage(HashMapStorage)]
-struct AsteroidField {
-    asteroids: Vec<Entity>,
-    tick: u16,
-    spawn_time: u16,
-    x_range: f32,
+pub struct AsteroidField {
+    pub asteroids: Vec<Entity>,
+    pub tick: u16,
+    pub spawn_time: u16,
+    pub x_range: f32,
 }
 
 struct AsteroidFieldSystem;
@@ -79,21 +78,14 @@ impl<'a> System<'a> for AsteroidFieldSystem {
                 transform.set_rotation_z(rng.gen_range(0.0..crate::PI * 2.0));
                 // TODO: Hide Spawning from Camera
                 // TODO: Never Spawn collision with ship!
-                let entity = lazy_update
-                    .create_entity(&entities)
-                    .with(transform)
-                    .with(Model::new(*meshes.asteroids.get(item).unwrap()))
-                    .with(RigidBody {
-                        velocity: Vector3::new(Asteroid::VELOCITY, 0.0, 0.0),
-                    })
-                    .with(Collider::new(
-                        Hitbox::with_shape(ColliderShape::Sphere(Asteroid::COLLIDER_RADIUS)),
-                        Collider::ASTEROID,
-                        vec![Collider::SHIP, Collider::MISSLE],
-                    ))
-                    .with(Asteroid(*item))
-                    .with(Health(Asteroid::HEALTH))
-                    .build();
+                let entity = objects::build_asteroid(
+                    &meshes,
+                    lazy_update.create_entity(&entities),
+                    *item,
+                    Asteroid::HEALTH,
+                    transform,
+                    Vector3::new(Asteroid::VELOCITY, 0.0, 0.0),
+                );
                 field.asteroids.push(entity);
             }
         }
@@ -0,0 +1,93 @@
+use super::Transform;
+use specs::{prelude::*, storage::MaskedStorage, Component};
+use std::collections::HashMap;
+use std::ops::Deref;
+
+pub fn register_components(world: &mut World) {
+    world.register::<Parent>();
+}
+
+/// Attaches an entity to another one, so that it moves and turns along with it.
+/// The entity's `Transform` is worked out from its parent's by the `TransformPropagationSystem`.
+/// Parents can have parents of their own, but they must not form a cycle.
+#[derive(Component, Clone)]
+#[storage(VecStorage)]
+pub struct Parent {
+    pub entity: Entity,
+    /// Where the entity sits relative to its parent
+    pub local: Transform,
+}
+
+/// Follows the parents of an entity up to the entity at the top of its hierarchy,
+/// such as the ship that a gadget is part of
+pub fn root<D>(parents: &Storage<Parent, D>, mut entity: Entity) -> Entity
+where
+    D: Deref<Target = MaskedStorage<Parent>>,
+{
+    while let Some(parent) = parents.get(entity) {
+        entity = parent.entity;
+    }
+    entity
+}
+
+/// Moves every entity with a parent to its place relative to that parent
+pub struct TransformPropagationSystem;
+
+impl<'a> System<'a> for TransformPropagationSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Parent>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, parents, mut transforms) = data;
+
+        // Every parent is worked out once, no matter how many children it has
+        let mut world_transforms = HashMap::new();
+        let mut moved = Vec::new();
+        for (entity, _) in (&entities, &parents).join() {
+            let transform =
+                match world_transform(entity, &parents, &transforms, &mut world_transforms) {
+                    Some(transform) => transform,
+                    None => continue,
+                };
+
+            // Only entities that actually moved are written to, since writing marks their models as modified
+            let unchanged = transforms.get(entity).is_some_and(|old| {
+                old.position == transform.position && old.rotation == transform.rotation
+            });
+            if !unchanged {
+                moved.push((entity, transform));
+            }
+        }
+
+        for (entity, transform) in moved {
+            transforms
+                .insert(entity, transform)
+                .expect("Unable to move child entity");
+        }
+    }
+}
+
+/// The transform of the entity in world space, once its parents have been moved.
+/// Returns None if anything in the hierarchy doesn't have a transform.
+fn world_transform(
+    entity: Entity,
+    parents: &ReadStorage<Parent>,
+    transforms: &WriteStorage<Transform>,
+    world_transforms: &mut HashMap<Entity, Transform>,
+) -> Option<Transform> {
+    if let Some(transform) = world_transforms.get(&entity) {
+        return Some(transform.clone());
+    }
+
+    let transform = match parents.get(entity) {
+        Some(parent) => world_transform(parent.entity, parents, transforms, world_transforms)?
+            .apply_to(&parent.local),
+        None => transforms.get(entity)?.clone(),
+    };
+    world_transforms.insert(entity, transform.clone());
+
+    Some(transform)
+}
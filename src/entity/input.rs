@@ -1,4 +1,4 @@
-use super::{Collider, RaycastWorld, WindowSize};
+use super::{Collider, PhysicsWorld, WindowSize};
 use crate::graphics::Camera;
 use cgmath::{InnerSpace, Point2, Vector3};
 use specs::prelude::*;
@@ -9,12 +9,16 @@ use winit::event;
 pub enum InputAction {
     Mining,
     Laser,
+    Build,
+    /// Locks or unlocks the doors that are clicked on
+    Doors,
     None,
 }
 
 pub struct InputManager {
     pub action: InputAction,
     pub left_mb: bool,
+    pub right_mb: bool,
     pub mouse_pos: Point2<f32>,
     pub keys: Keys,
     pub target: Option<Entity>,
@@ -25,6 +29,7 @@ impl InputManager {
         Self {
             action: InputAction::None,
             left_mb: false,
+            right_mb: false,
             mouse_pos: Point2::new(0.0, 0.0),
             target: None,
             keys: Keys(HashSet::new()),
@@ -32,6 +37,24 @@ impl InputManager {
     }
 }
 
+/// Returns the near and far points of the ray going through the mouse cursor
+pub fn mouse_ray(
+    input: &InputManager,
+    camera: &Camera,
+    window_size: &WindowSize,
+) -> (Vector3<f32>, Vector3<f32>) {
+    let near = camera.unproject(
+        Vector3::new(input.mouse_pos.x, input.mouse_pos.y, 0.0),
+        window_size.as_point(),
+    );
+    let far = camera.unproject(
+        Vector3::new(input.mouse_pos.x, input.mouse_pos.y, 1.0),
+        window_size.as_point(),
+    );
+
+    (near, far)
+}
+
 pub struct Keys(HashSet<event::VirtualKeyCode>);
 
 impl Keys {
@@ -42,7 +65,7 @@ impl Keys {
         };
     }
 
-    fn is_key_down(&self, key: event::VirtualKeyCode) -> bool {
+    pub fn is_key_down(&self, key: event::VirtualKeyCode) -> bool {
         self.0.contains(&key)
     }
 }
@@ -92,7 +115,7 @@ impl<'a> System<'a> for InputSystem {
         WriteExpect<'a, InputManager>,
         ReadExpect<'a, Camera>,
         ReadExpect<'a, WindowSize>,
-        ReadExpect<'a, RaycastWorld>,
+        ReadExpect<'a, PhysicsWorld>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -103,17 +126,10 @@ impl<'a> System<'a> for InputSystem {
             return;
         }
 
-        let near = camera.unproject(
-            Vector3::new(input.mouse_pos.x, input.mouse_pos.y, 0.0),
-            window_size.as_point(),
-        );
-        let far = camera.unproject(
-            Vector3::new(input.mouse_pos.x, input.mouse_pos.y, 1.0),
-            window_size.as_point(),
-        );
-
+        let (near, far) = mouse_ray(&input, &camera, &window_size);
         input.target = match input.action {
             InputAction::Mining | InputAction::Laser => Some(vec![Collider::ASTEROID]),
+            InputAction::Doors => Some(vec![Collider::SHIP]),
             _ => None,
         }
         .and_then(|collider| raycaster.raycast(collider, near, far));
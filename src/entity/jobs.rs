@@ -0,0 +1,359 @@
+use super::{crew::Crew, hierarchy::Parent, objects::Health, ship, BlockEntity, Ship};
+use crate::block::{BlockId, Blocks, Door};
+use cgmath::Point2;
+use specs::{prelude::*, Component};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+pub fn register_components(world: &mut World) {
+    world.register::<Construction>();
+    world.register::<OperatorStation>();
+    world.register::<JobQueue>();
+}
+
+pub fn setup_systems(builder: &mut DispatcherBuilder) {
+    builder.add(JobSystem, "job_system", &["crew_system"]);
+}
+
+/// A block that has been placed, but not built yet.
+/// It doesn't do anything until a crew member has finished building it.
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct Construction {
+    /// Seconds of work that have gone into the block so far
+    pub progress: f32,
+}
+
+/// A block that only works while a crew member is manning it
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct OperatorStation {
+    operator: Option<Entity>,
+}
+
+impl OperatorStation {
+    pub fn is_manned(&self) -> bool {
+        self.operator.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JobKind {
+    Construct,
+    Repair,
+    Operate,
+}
+
+impl JobKind {
+    /// Jobs with a higher priority are handed out first
+    pub fn priority(self) -> u8 {
+        match self {
+            JobKind::Repair => 2,
+            JobKind::Operate => 1,
+            JobKind::Construct => 0,
+        }
+    }
+}
+
+/// Work that needs doing on a block
+pub struct Job {
+    pub kind: JobKind,
+    /// The block to work on
+    pub target: Entity,
+    /// The crew member that the job has been given to
+    pub worker: Option<Entity>,
+    /// Repairs that haven't added up to a whole point of health yet
+    repairs: f32,
+}
+
+impl Job {
+    /// Health restored every second by a crew member doing repairs
+    const REPAIR_RATE: f32 = 10.0;
+
+    fn new(kind: JobKind, target: Entity) -> Self {
+        Self {
+            kind,
+            target,
+            worker: None,
+            repairs: 0.0,
+        }
+    }
+}
+
+/// The jobs that need doing on a ship, with the highest priority jobs first
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct JobQueue {
+    jobs: Vec<Job>,
+    /// Seconds until idle crew members are given jobs again
+    assign_timer: f32,
+}
+
+impl JobQueue {
+    /// Finding paths for every idle crew member is slow, so jobs are only handed out this often
+    const ASSIGN_INTERVAL: f32 = 0.5;
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    /// Adds the jobs that are needed, and drops any that aren't.
+    /// Crew members working on a dropped job are let go.
+    fn update(&mut self, needed: &[(Entity, JobKind)], crews: &mut WriteStorage<Crew>) {
+        let needed_set: HashSet<(Entity, JobKind)> = needed.iter().copied().collect();
+        self.jobs.retain(|job| {
+            let keep = needed_set.contains(&(job.target, job.kind));
+            if !keep {
+                let crew = job.worker.and_then(|worker| crews.get_mut(worker));
+                if let Some(crew) = crew.filter(|crew| crew.job() == Some(job.target)) {
+                    crew.finish_job();
+                }
+            }
+            keep
+        });
+
+        for (target, kind) in needed {
+            if !self
+                .jobs
+                .iter()
+                .any(|job| job.target == *target && job.kind == *kind)
+            {
+                self.jobs.push(Job::new(*kind, *target));
+            }
+        }
+        // The sort is stable, so older jobs go first within each priority
+        self.jobs.sort_by_key(|job| Reverse(job.kind.priority()));
+    }
+}
+
+/// How far along each construction site on a ship is, keyed by the root tile and type of its block
+pub type ConstructionSites = HashMap<(Point2<i16>, BlockId), f32>;
+
+/// Finds the blocks and gadgets on the ship that haven't been built yet
+pub fn construction_sites(world: &World, ship: Entity) -> ConstructionSites {
+    let ships = world.read_component::<Ship>();
+    let block_entities = world.read_component::<BlockEntity>();
+    let constructions = world.read_component::<Construction>();
+    let ship = match ships.get(ship) {
+        Some(ship) => ship,
+        None => return ConstructionSites::new(),
+    };
+
+    ship.blocks()
+        .into_iter()
+        .chain(ship.gadgets())
+        .filter_map(|entity| {
+            let block_entity = block_entities.get(entity)?;
+            let construction = constructions.get(entity)?;
+            Some((
+                (block_entity.root(), block_entity.block_id()),
+                construction.progress,
+            ))
+        })
+        .collect()
+}
+
+/// Turns blocks into construction sites, which the crew have to build before they work.
+/// Each block starts with the given progress.
+pub fn start_construction(world: &World, sites: &[(Entity, f32)]) {
+    let lazy_update = world.fetch::<LazyUpdate>();
+    for (entity, progress) in sites {
+        lazy_update.insert(
+            *entity,
+            Construction {
+                progress: *progress,
+            },
+        );
+    }
+}
+
+/// The tiles that a crew member can work on a block from,
+/// which are the walkable tiles around it (including diagonally)
+fn work_tiles(
+    ship: &Ship,
+    blocks: &Blocks,
+    block_entity: &BlockEntity,
+    is_passable: impl Fn(Entity) -> bool + Copy,
+) -> Vec<Point2<i16>> {
+    let block = blocks.get_block(block_entity.block_id());
+    let size = block_entity.orientation().rotate_size(block.size);
+    // Blocks already on the ship always fit within the tile positions
+    let footprint = ship::footprint(block_entity.root(), size).unwrap_or_default();
+
+    let mut tiles: Vec<Point2<i16>> = footprint
+        .iter()
+        .flat_map(|pos| {
+            (-1..=1).flat_map(move |x| {
+                (-1..=1).filter_map(move |y| {
+                    Some(Point2::new(pos.x.checked_add(x)?, pos.y.checked_add(y)?))
+                })
+            })
+        })
+        .filter(|pos| !footprint.contains(pos) && ship.is_walkable(*pos, is_passable))
+        .collect();
+    tiles.sort_by_key(|pos| (pos.x, pos.y));
+    tiles.dedup();
+    tiles
+}
+
+/// Keeps the job queue of every ship up to date, hands jobs out to the nearest free
+/// crew members, and carries out the work of the crew members who have reached their jobs
+pub struct JobSystem;
+
+impl<'a> System<'a> for JobSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Blocks>,
+        WriteStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        WriteStorage<'a, JobQueue>,
+        WriteStorage<'a, Crew>,
+        ReadStorage<'a, Parent>,
+        WriteStorage<'a, Construction>,
+        WriteStorage<'a, Health>,
+        WriteStorage<'a, OperatorStation>,
+        ReadStorage<'a, Door>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            blocks,
+            mut ships,
+            block_entities,
+            mut queues,
+            mut crews,
+            parents,
+            mut constructions,
+            mut healths,
+            mut stations,
+            doors,
+        ) = data;
+        let dt = 1.0 / 60.0;
+
+        for (ship_entity, ship, queue) in (&entities, &mut ships, &mut queues).join() {
+            let parts: Vec<Entity> = ship.blocks().into_iter().chain(ship.gadgets()).collect();
+            let needed: Vec<(Entity, JobKind)> = parts
+                .iter()
+                .filter_map(|entity| {
+                    let block = blocks.get_block(block_entities.get(*entity)?.block_id());
+                    let kind = if constructions.contains(*entity) {
+                        JobKind::Construct
+                    } else if healths
+                        .get(*entity)
+                        .is_some_and(|health| health.health() < block.health)
+                    {
+                        JobKind::Repair
+                    } else if block.needs_operator {
+                        JobKind::Operate
+                    } else {
+                        return None;
+                    };
+                    Some((*entity, kind))
+                })
+                .collect();
+            queue.update(&needed, &mut crews);
+
+            queue.assign_timer -= dt;
+            if queue.assign_timer <= 0.0 {
+                queue.assign_timer = JobQueue::ASSIGN_INTERVAL;
+
+                let mut available: Vec<Entity> = (&entities, &crews, &parents)
+                    .join()
+                    .filter(|(_, crew, parent)| parent.entity == ship_entity && crew.is_available())
+                    .map(|(entity, _, _)| entity)
+                    .collect();
+                let is_passable = |entity| {
+                    doors.get(entity).is_some_and(Door::is_passable)
+                        && !constructions.contains(entity)
+                };
+
+                for job in queue.jobs.iter_mut().filter(|job| job.worker.is_none()) {
+                    let block_entity = match block_entities.get(job.target) {
+                        Some(block_entity) => block_entity,
+                        None => continue,
+                    };
+                    let tiles = work_tiles(ship, &blocks, block_entity, is_passable);
+
+                    // The job goes to whoever can get to it the quickest
+                    let nearest = available
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(i, worker)| {
+                            let start = crews.get(*worker)?.tile();
+                            let mut goals = tiles.clone();
+                            goals.sort_by_key(|pos| {
+                                (i32::from(pos.x) - i32::from(start.x)).abs()
+                                    + (i32::from(pos.y) - i32::from(start.y)).abs()
+                            });
+                            let path = goals
+                                .into_iter()
+                                .find_map(|goal| ship.find_path(start, goal, is_passable))?;
+                            Some((i, path))
+                        })
+                        .min_by_key(|(_, path)| path.len());
+
+                    if let Some((i, path)) = nearest {
+                        let worker = available.swap_remove(i);
+                        crews.get_mut(worker).unwrap().assign_job(job.target, path);
+                        job.worker = Some(worker);
+                    }
+                    if available.is_empty() {
+                        break;
+                    }
+                }
+            }
+
+            let mut operators = HashMap::new();
+            let mut finished = false;
+            for job in &mut queue.jobs {
+                // Crew members drop their jobs when they run out of breath, or can't get to them
+                let worker = job
+                    .worker
+                    .filter(|worker| crews.get(*worker).and_then(Crew::job) == Some(job.target));
+                job.worker = worker;
+                let worker = match worker {
+                    Some(worker) if crews.get(worker).unwrap().is_working() => worker,
+                    _ => continue,
+                };
+                let block = match block_entities.get(job.target) {
+                    Some(block_entity) => blocks.get_block(block_entity.block_id()),
+                    None => continue,
+                };
+
+                match job.kind {
+                    JobKind::Construct => {
+                        if let Some(construction) = constructions.get_mut(job.target) {
+                            construction.progress += dt;
+                            if construction.progress >= block.build_time {
+                                constructions.remove(job.target);
+                                finished = true;
+                            }
+                        }
+                    }
+                    JobKind::Repair => {
+                        job.repairs += Job::REPAIR_RATE * dt;
+                        let amount = job.repairs.floor();
+                        job.repairs -= amount;
+                        if let Some(health) = healths.get_mut(job.target) {
+                            health.repair(amount as u32, block.health);
+                        }
+                    }
+                    JobKind::Operate => {
+                        operators.insert(job.target, worker);
+                    }
+                }
+            }
+            // Finished walls can seal rooms, and finished blocks get in the crew's way
+            if finished {
+                ship.layout_changed();
+            }
+
+            for entity in &parts {
+                if let Some(station) = stations.get_mut(*entity) {
+                    station.operator = operators.get(entity).copied();
+                }
+            }
+        }
+    }
+}
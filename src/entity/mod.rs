@@ -1,23 +1,42 @@
 use crate::graphics::{Camera, MeshId, MeshManager, ModelId};
-use crate::{block::Blocks, floor::Floors};
-use cgmath::{prelude::*, Matrix4, Point2, Quaternion, Vector3};
+use crate::{
+    block::{Blocks, Laser, Miner, MinerSettings, TargetMode},
+    floor::Floors,
+};
+pub use blueprint::{Blueprint, BlueprintError};
+pub use build::{BuildChange, BuildHistory, BuildMode, BuildSelection};
+use cgmath::{prelude::*, Matrix4, Point2, Quaternion, Vector2, Vector3};
+pub use hierarchy::Parent;
 pub use input::{InputAction, InputManager};
 pub use objects::ObjectMeshes;
-pub use physics::{Collider, ColliderShape, Hitbox, RaycastWorld, RigidBody};
-pub use ship::{BlockEntity, Ship, Tile};
-use specs::{prelude::*, shred::Fetch, storage::MaskedStorage, Component};
-
+pub use physics::{Collider, ColliderShape, Hitbox, PhysicsWorld, RigidBody};
+pub use save::SaveError;
+use serde::{Deserialize, Serialize};
+pub use ship::{BlockEntity, BuildAction, FloorEntity, PlayerShip, Ship, TileBounds};
+use specs::{prelude::*, shrev::EventChannel, Component};
+use std::path::Path;
+
+pub mod atmosphere;
+pub mod blueprint;
+pub mod build;
+pub mod crew;
 pub mod gameplay;
+pub mod hierarchy;
 pub mod input;
+pub mod jobs;
+pub mod movement;
 pub mod objects;
 pub mod physics;
+pub mod power;
+pub mod projectile;
+pub mod save;
 pub mod ship;
-
-pub type SimpleStorage<'a, T> = Storage<'a, T, Fetch<'a, MaskedStorage<T>>>;
+pub mod structure;
 
 pub struct Model {
     pub mesh_id: MeshId,
-    model_id: Option<ModelId>,
+    /// The model inside of the MeshManager, along with the mesh it was created for
+    model_id: Option<(MeshId, ModelId)>,
 }
 
 impl Component for Model {
@@ -40,23 +59,28 @@ pub struct ModelUpdateSystem {
     model_reader: ReaderId<ComponentEvent>,
     inserted: BitSet,
     modified: BitSet,
+    /// Models that were changed, which may now use a different mesh
+    remeshed: BitSet,
 }
 
 impl<'a> System<'a> for ModelUpdateSystem {
     type SystemData = (
+        Entities<'a>,
         WriteExpect<'a, MeshManager>,
         ReadStorage<'a, Transform>,
         WriteStorage<'a, Model>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut mesh_manager, transforms, mut models) = data;
+        let (entities, mut mesh_manager, transforms, mut models) = data;
         self.inserted.clear();
         self.modified.clear();
+        self.remeshed.clear();
 
         for event in models.channel().read(&mut self.model_reader) {
             match event {
                 ComponentEvent::Inserted(id) => self.inserted.add(*id),
+                ComponentEvent::Modified(id) => self.remeshed.add(*id),
                 _ => false,
             };
         }
@@ -69,18 +93,32 @@ impl<'a> System<'a> for ModelUpdateSystem {
         }
 
         for (model, transform, _) in (&mut models, &transforms, &self.inserted).join() {
-            model.model_id = Some(mesh_manager.new_model(model.mesh_id, transform.as_matrix()));
+            let model_id = mesh_manager.new_model(model.mesh_id, transform.as_matrix());
+            model.model_id = Some((model.mesh_id, model_id));
         }
 
-        for (model, transform, _) in (&mut models, &transforms, &self.modified)
-            .join()
-            .filter(|(model, _, _)| model.model_id.is_some())
-        {
-            mesh_manager.update_model(
-                model.mesh_id,
-                model.model_id.unwrap(),
-                transform.as_matrix(),
-            );
+        // Only models whose mesh changed are written to, since writing
+        // to a model would mark it as modified again on the next update
+        let remeshed: Vec<(Entity, Matrix4<f32>)> =
+            (&entities, &models, &transforms, &self.remeshed)
+                .join()
+                .filter(|(_, model, _, _)| {
+                    matches!(model.model_id, Some((mesh_id, _)) if mesh_id != model.mesh_id)
+                })
+                .map(|(entity, _, transform, _)| (entity, transform.as_matrix()))
+                .collect();
+        for (entity, matrix) in remeshed {
+            let model = models.get_mut(entity).unwrap();
+            if let Some((mesh_id, model_id)) = model.model_id {
+                mesh_manager.remove_model(mesh_id, model_id);
+            }
+            model.model_id = Some((model.mesh_id, mesh_manager.new_model(model.mesh_id, matrix)));
+        }
+
+        for (model, transform, _) in (&models, &transforms, &self.modified).join() {
+            if let Some((mesh_id, model_id)) = model.model_id {
+                mesh_manager.update_model(mesh_id, model_id, transform.as_matrix());
+            }
         }
     }
 }
@@ -98,8 +136,8 @@ impl<'a> System<'a> for RemoveModelSystem {
         let (to_be_removed, mut mesh_manager, mut models) = data;
 
         for (model, _) in (&mut models, to_be_removed.bitset()).join() {
-            if let Some(model_id) = model.model_id {
-                mesh_manager.remove_model(model.mesh_id, model_id);
+            if let Some((mesh_id, model_id)) = model.model_id {
+                mesh_manager.remove_model(mesh_id, model_id);
                 model.model_id = None;
             }
         }
@@ -114,21 +152,22 @@ pub struct ECS<'a> {
 
 impl<'a> ECS<'a> {
     pub fn new(
-        device: &wgpu::Device,
         mut mesh_manager: MeshManager,
         blocks: Blocks,
         floors: Floors,
         camera: Camera,
         window_size: WindowSize,
     ) -> Self {
-        let meshes = ObjectMeshes::load(device, &mut mesh_manager);
-        let hitbox_meshes = physics::HitboxMeshes::load(device, &mut mesh_manager);
+        let meshes = ObjectMeshes::load(&mut mesh_manager);
+        let hitbox_meshes = physics::HitboxMeshes::load(&mut mesh_manager);
+        let projectiles = projectile::load_projectiles(&mut mesh_manager);
         let inventory = crate::item::Inventory::new();
 
         let mut world = World::new();
         world.register::<Model>();
         world.register::<Ship>();
         world.register::<BlockEntity>();
+        world.register::<FloorEntity>();
         world.register::<Transform>();
         world.register::<RigidBody>();
         world.register::<Collider>();
@@ -136,16 +175,30 @@ impl<'a> ECS<'a> {
         world.insert(ToBeRemoved::default());
         world.insert(meshes);
         world.insert(hitbox_meshes);
+        world.insert(projectiles);
         world.insert(mesh_manager);
         world.insert(blocks);
         world.insert(floors);
         world.insert(camera);
         world.insert(window_size);
         world.insert(inventory);
-        world.insert(RaycastWorld::new());
+        world.insert(PhysicsWorld::new());
         world.insert(InputManager::new());
+        world.insert(BuildMode::default());
+        world.insert(BuildHistory::default());
+        world.insert(power::PowerStatus::default());
+        world.insert(DebugOverlay::default());
+        world.insert(EventChannel::<physics::CollisionEvent>::new());
         objects::register_components(&mut world);
         gameplay::register_components(&mut world);
+        structure::register_components(&mut world);
+        power::register_components(&mut world);
+        movement::register_components(&mut world);
+        hierarchy::register_components(&mut world);
+        atmosphere::register_components(&mut world);
+        crew::register_components(&mut world);
+        jobs::register_components(&mut world);
+        projectile::register_components(&mut world);
         crate::block::register_components(&mut world);
 
         let model_update_system = {
@@ -156,34 +209,74 @@ impl<'a> ECS<'a> {
                 model_reader,
                 inserted: BitSet::new(),
                 modified: BitSet::new(),
+                remeshed: BitSet::new(),
             }
         };
 
+        let physics_system = physics::PhysicsSystem::new(&mut world);
+        let hitbox_model_system = physics::HitboxModelSystem::new(&mut world);
+        let asteroid_impact_system = objects::AsteroidImpactSystem::new(&mut world);
+        let projectile_impact_system = projectile::ProjectileImpactSystem::new(&mut world);
+
         let mut dispatcher_builder = DispatcherBuilder::new()
             .with(input::CameraSystem, "camera_system", &[])
             .with(input::InputSystem, "input_system", &["camera_system"]);
         dispatcher_builder.add_barrier();
+        dispatcher_builder.add(build::BuildSystem, "build_system", &[]);
+        dispatcher_builder.add(movement::ShipMovementSystem, "ship_movement_system", &[]);
         crate::block::setup_systems(&mut dispatcher_builder);
         objects::setup_systems(&mut dispatcher_builder);
+        projectile::setup_systems(&mut dispatcher_builder);
         gameplay::setup_systems(&mut dispatcher_builder);
+        structure::setup_systems(&mut dispatcher_builder);
+        atmosphere::setup_systems(&mut dispatcher_builder);
+        crew::setup_systems(&mut dispatcher_builder);
+        jobs::setup_systems(&mut dispatcher_builder);
         dispatcher_builder.add_barrier();
         let dispatcher = dispatcher_builder
-            .with(physics::PhysicsSystem, "physics_system", &[])
+            .with(physics::RigidBodySystem, "rigid_body_system", &[])
+            .with(
+                hierarchy::TransformPropagationSystem,
+                "transform_propagation_system",
+                &["rigid_body_system"],
+            )
+            .with(
+                physics_system,
+                "physics_system",
+                &["transform_propagation_system"],
+            )
             .with(
-                physics::RaycastSystem,
-                "raycast_system",
+                asteroid_impact_system,
+                "asteroid_impact_system",
                 &["physics_system"],
             )
-            .with(model_update_system, "update_models", &["raycast_system"])
+            .with(
+                projectile_impact_system,
+                "projectile_impact_system",
+                &["physics_system"],
+            )
+            .with(power::PowerSystem, "power_system", &[])
+            .with(
+                hitbox_model_system,
+                "hitbox_model_system",
+                &["transform_propagation_system"],
+            )
+            .with(
+                model_update_system,
+                "update_models",
+                &["hitbox_model_system"],
+            )
             .build();
 
         let death_dispatcher = DispatcherBuilder::new()
             .with(objects::AsteroidMinedSystem, "", &[])
             .with(RemoveModelSystem, "", &[])
-            .with(physics::RemoveRaycastColliderSystem, "", &[])
+            .with(physics::RemoveColliderSystem, "", &[])
             .build();
 
-        ship::create_ship(&mut world);
+        let player_ship = ship::create_ship(&mut world);
+        world.insert(PlayerShip(player_ship));
+        crew::spawn_crew(&mut world, player_ship, crew::Crew::STARTING_CREW);
         gameplay::init_world(&mut world);
 
         ECS {
@@ -193,11 +286,125 @@ impl<'a> ECS<'a> {
         }
     }
 
+    /// Creates an ECS that is not backed by a GPU. Meshes are still registered
+    /// and models are still tracked by the MeshManager, but nothing is ever
+    /// uploaded, so the world can be stepped on machines without a graphics device.
+    pub fn headless() -> Self {
+        let mut mesh_manager = MeshManager::new();
+        let blocks = crate::block::load_blocks(&mut mesh_manager);
+        let floors = crate::floor::load_floors(&mut mesh_manager);
+        let window_size = WindowSize {
+            width: 1280.0,
+            height: 720.0,
+        };
+        let camera = Camera::new(window_size.width / window_size.height);
+
+        Self::new(mesh_manager, blocks, floors, camera, window_size)
+    }
+
     pub fn update(&mut self) {
+        self.world.fetch_mut::<DebugOverlay>().lines.clear();
         self.dispatcher.dispatch(&self.world);
+        self.execute_player_builds();
         self.maintain();
     }
 
+    /// Executes the build actions that the player made during this update
+    fn execute_player_builds(&mut self) {
+        let actions = self.world.fetch_mut::<BuildMode>().take_pending();
+        if actions.is_empty() {
+            return;
+        }
+
+        let inverse = self.execute_player_actions(&actions, None);
+        self.world.fetch_mut::<BuildHistory>().record(inverse);
+    }
+
+    /// Undoes the player's most recent change to their ship. Changes to blocks that are no
+    /// longer on the ship can't be undone, and are dropped from the history.
+    pub fn undo_build(&mut self) {
+        let change = self.world.fetch_mut::<BuildHistory>().take_undo();
+        if let Some(change) = change.filter(|change| self.still_applies(change)) {
+            let inverse = self.execute_player_actions(&change.actions, Some(&change.construction));
+            self.world.fetch_mut::<BuildHistory>().push_redo(inverse);
+            self.maintain();
+        }
+    }
+
+    /// Redoes the change that was most recently undone, unless it has stopped matching the ship
+    pub fn redo_build(&mut self) {
+        let change = self.world.fetch_mut::<BuildHistory>().take_redo();
+        if let Some(change) = change.filter(|change| self.still_applies(change)) {
+            let inverse = self.execute_player_actions(&change.actions, Some(&change.construction));
+            self.world.fetch_mut::<BuildHistory>().push_undo(inverse);
+            self.maintain();
+        }
+    }
+
+    /// Executes build actions on the player's ship, paid for with the inventory.
+    /// Everything the player places has to be built by the crew, but blocks brought back by an
+    /// undo or redo are only construction sites if `construction` says they were when removed.
+    /// Returns the change that will undo the actions.
+    fn execute_player_actions(
+        &mut self,
+        actions: &[BuildAction],
+        construction: Option<&jobs::ConstructionSites>,
+    ) -> BuildChange {
+        let ship = self.world.fetch::<PlayerShip>().0;
+        let sites = jobs::construction_sites(&self.world, ship);
+        let outcome =
+            ship::execute_build_actions(&mut self.world, ship, actions, ship::Payment::Inventory);
+        for err in outcome.errors {
+            println!("[Build] Unable to build: {:?}", err);
+        }
+
+        let started: Vec<(Entity, f32)> = outcome
+            .built
+            .iter()
+            .filter_map(|(entity, block_entity)| {
+                let key = (block_entity.root(), block_entity.block_id());
+                match construction {
+                    Some(construction) => {
+                        construction.get(&key).map(|progress| (*entity, *progress))
+                    }
+                    None => Some((*entity, 0.0)),
+                }
+            })
+            .collect();
+        jobs::start_construction(&self.world, &started);
+
+        // Construction sites that were removed go back to how far along they were if the removal is undone
+        let construction = outcome
+            .inverse
+            .iter()
+            .filter_map(|action| match action {
+                BuildAction::BuildBlock(pos, block_id, _) => {
+                    let key = (*pos, *block_id);
+                    sites.get(&key).map(|progress| (key, *progress))
+                }
+                _ => None,
+            })
+            .collect();
+
+        let ships = self.world.read_component::<Ship>();
+        match ships.get(ship) {
+            Some(ship) => BuildChange::new(outcome.inverse, construction, ship),
+            None => BuildChange::default(),
+        }
+    }
+
+    /// Whether the change still matches the player's ship, which prints why not if it doesn't
+    fn still_applies(&self, change: &BuildChange) -> bool {
+        let ships = self.world.read_component::<Ship>();
+        let applies = ships
+            .get(self.world.fetch::<PlayerShip>().0)
+            .is_some_and(|ship| change.still_applies(ship));
+        if !applies {
+            println!("[Build] The ship has changed since then, so the change was dropped");
+        }
+        applies
+    }
+
     pub fn maintain(&mut self) {
         self.death_dispatcher.dispatch(&self.world);
         {
@@ -213,6 +420,147 @@ impl<'a> ECS<'a> {
         self.world.maintain();
     }
 
+    /// Writes the entire game state to a save file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let path = path.as_ref();
+        let text = save::write_save(&self.world)?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, text)?;
+
+        Ok(())
+    }
+
+    /// Replaces the current game state with the contents of a save file.
+    /// The current world is left untouched if the save file is invalid.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let text = std::fs::read_to_string(path)?;
+        let save = save::read_save(&text)?;
+        let actions = save::ship_build_actions(&self.world, &save)?;
+
+        self.clear();
+        save::restore_world(&mut self.world, save, actions);
+        // The history refers to tiles of the old ship
+        self.world.insert(BuildHistory::default());
+
+        Ok(())
+    }
+
+    /// Writes the layout of the player's ship to a blueprint file.
+    pub fn export_blueprint(&self, path: impl AsRef<Path>) -> Result<(), BlueprintError> {
+        let ships = self.world.read_component::<Ship>();
+        let ship = ships
+            .get(self.world.fetch::<PlayerShip>().0)
+            .ok_or(BlueprintError::NoShip)?;
+
+        Blueprint::from_ship(&self.world, ship).save(path)
+    }
+
+    /// Limits the area that the player's ship can be built in, or lets it grow
+    /// without any limit. Anything already built outside of the limits stays.
+    pub fn set_ship_limits(&mut self, limits: Option<TileBounds>) {
+        let player_ship = self.world.fetch::<PlayerShip>().0;
+        if let Some(ship) = self.world.write_component::<Ship>().get_mut(player_ship) {
+            ship.set_limits(limits);
+        }
+    }
+
+    /// How the miners on the player's ship are set up, if it has any miners
+    pub fn miner_settings(&self) -> Option<MinerSettings> {
+        let player_ship = self.world.fetch::<PlayerShip>().0;
+        let parents = self.world.read_component::<Parent>();
+        let miners = self.world.read_component::<Miner>();
+
+        (&self.world.entities(), &miners)
+            .join()
+            .find(|(entity, _)| hierarchy::root(&parents, *entity) == player_ship)
+            .map(|(_, miner)| miner.settings)
+    }
+
+    /// Changes the settings of every miner on the player's ship
+    pub fn set_miner_settings(&mut self, settings: MinerSettings) {
+        let player_ship = self.world.fetch::<PlayerShip>().0;
+        let parents = self.world.read_component::<Parent>();
+        let mut miners = self.world.write_component::<Miner>();
+
+        for (entity, miner) in (&self.world.entities(), &mut miners).join() {
+            if hierarchy::root(&parents, entity) == player_ship {
+                miner.settings = settings;
+            }
+        }
+    }
+
+    /// How the lasers on the player's ship pick their targets, if it has any lasers
+    pub fn laser_targeting(&self) -> Option<TargetMode> {
+        let player_ship = self.world.fetch::<PlayerShip>().0;
+        let parents = self.world.read_component::<Parent>();
+        let lasers = self.world.read_component::<Laser>();
+
+        (&self.world.entities(), &lasers)
+            .join()
+            .find(|(entity, _)| hierarchy::root(&parents, *entity) == player_ship)
+            .map(|(_, laser)| laser.mode)
+    }
+
+    /// Switches every laser on the player's ship over to the next way of picking targets
+    pub fn cycle_laser_targeting(&mut self) {
+        if let Some(mode) = self.laser_targeting() {
+            self.set_laser_targeting(mode.next());
+        }
+    }
+
+    /// Changes how every laser on the player's ship picks its targets
+    pub fn set_laser_targeting(&mut self, mode: TargetMode) {
+        let player_ship = self.world.fetch::<PlayerShip>().0;
+        let parents = self.world.read_component::<Parent>();
+        let mut lasers = self.world.write_component::<Laser>();
+
+        for (entity, laser) in (&self.world.entities(), &mut lasers).join() {
+            if hierarchy::root(&parents, entity) == player_ship {
+                laser.mode = mode;
+                laser.target = None;
+            }
+        }
+    }
+
+    /// Builds the blueprint into the player's ship, moved by the offset.
+    /// The blocks are paid for with the inventory, and the stamp can be undone.
+    pub fn stamp_blueprint(
+        &mut self,
+        path: impl AsRef<Path>,
+        offset: Vector2<i16>,
+    ) -> Result<(), BlueprintError> {
+        let blueprint = Blueprint::load(path)?;
+        let actions = {
+            let ships = self.world.read_component::<Ship>();
+            let ship = ships
+                .get(self.world.fetch::<PlayerShip>().0)
+                .ok_or(BlueprintError::NoShip)?;
+            blueprint.build_actions(&self.world, ship, offset)?
+        };
+
+        let inverse = self.execute_player_actions(&actions, None);
+        self.world.fetch_mut::<BuildHistory>().record(inverse);
+        self.maintain();
+
+        Ok(())
+    }
+
+    /// Removes every entity from the world
+    fn clear(&mut self) {
+        {
+            let entities = self.world.entities();
+            let mut to_be_removed = self.world.write_resource::<ToBeRemoved>();
+
+            for entity in entities.join() {
+                to_be_removed.add(entity);
+            }
+        }
+        self.maintain();
+    }
+
     pub fn get_resource_mut<T: 'static + Sync + Send>(&self) -> specs::shred::FetchMut<T> {
         self.world.write_resource::<T>()
     }
@@ -254,7 +602,7 @@ impl ToBeRemoved {
 }
 
 /// Represents an entity's position, rotation, and scale within space.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Transform {
     pub position: Vector3<f32>,
     pub rotation: Quaternion<f32>,
@@ -278,6 +626,31 @@ impl Transform {
         self.rotation = Quaternion::from_angle_z(cgmath::Rad(theta));
     }
 
+    /// Converts a transform that is relative to this one into world space.
+    /// The scale of this transform isn't applied, so children keep their own size.
+    pub fn apply_to(&self, local: &Transform) -> Transform {
+        Transform {
+            position: self.position + self.rotation.rotate_vector(local.position),
+            rotation: self.rotation * local.rotation,
+            scale: local.scale,
+        }
+    }
+
+    /// The inverse of `apply_to`, which finds where this transform sits relative to the parent
+    pub fn relative_to(&self, parent: &Transform) -> Transform {
+        let inverse = parent.rotation.invert();
+        Transform {
+            position: inverse.rotate_vector(self.position - parent.position),
+            rotation: inverse * self.rotation,
+            scale: self.scale,
+        }
+    }
+
+    /// Converts a point in world space into a point relative to this transform
+    pub fn to_local(&self, point: Vector3<f32>) -> Vector3<f32> {
+        self.rotation.invert().rotate_vector(point - self.position)
+    }
+
     fn as_matrix(&self) -> Matrix4<f32> {
         Matrix4::from_translation(self.position)
             * Matrix4::from(self.rotation)
@@ -298,6 +671,14 @@ pub struct Line {
 unsafe impl bytemuck::Pod for Line {}
 unsafe impl bytemuck::Zeroable for Line {}
 
+/// Extra lines drawn over the world to help with debugging, which are rebuilt every update
+#[derive(Default)]
+pub struct DebugOverlay {
+    /// Outline every room, coloured by how much air it holds. Toggled with F3.
+    pub show_rooms: bool,
+    pub lines: Vec<Line>,
+}
+
 pub struct WindowSize {
     pub width: f32,
     pub height: f32,
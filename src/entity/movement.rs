@@ -0,0 +1,211 @@
+use super::{
+    input::InputManager,
+    jobs::Construction,
+    power::PowerConsumer,
+    ship::{self, PlayerShip},
+    BlockEntity, RigidBody, Ship, Transform,
+};
+use crate::block::Blocks;
+use cgmath::{prelude::*, Quaternion, Rad, Vector2};
+use specs::{prelude::*, Component};
+use winit::event::VirtualKeyCode;
+
+pub fn register_components(world: &mut World) {
+    world.register::<ShipBody>();
+}
+
+/// The spin of a ship. Ships move with their RigidBody, and turn around their centre of mass.
+#[derive(Component, Default, Clone, Copy)]
+#[storage(HashMapStorage)]
+pub struct ShipBody {
+    /// Counter-clockwise turn rate in radians per second
+    pub angular_velocity: f32,
+}
+
+impl ShipBody {
+    /// The share of a ship's speed (and spin) that it loses every second, so that
+    /// ships settle down once their engines stop
+    const LINEAR_DRAG: f32 = 0.1;
+    const ANGULAR_DRAG: f32 = 0.5;
+    /// Ships moving (or turning) slower than this are brought to a stop
+    const REST_SPEED: f32 = 0.001;
+}
+
+/// The mass of a ship, and how that mass is spread around the ship's grid
+struct MassProperties {
+    mass: f32,
+    /// The centre of mass, in ship tiles
+    center: Vector2<f32>,
+    /// How hard the ship is to turn around its centre of mass
+    inertia: f32,
+}
+
+impl MassProperties {
+    fn of(ship: &Ship, blocks: &Blocks, block_entities: &ReadStorage<BlockEntity>) -> Self {
+        // Every block and gadget is treated as a point mass at the centre of its footprint
+        let masses: Vec<(f32, Vector2<f32>, f32)> = ship
+            .blocks()
+            .into_iter()
+            .chain(ship.gadgets())
+            .filter_map(|entity| block_entities.get(entity))
+            .map(|block_entity| {
+                let block = blocks.get_block(block_entity.block_id());
+                let size = block_entity.orientation().rotate_size(block.size);
+                let center = ship::footprint_center(block_entity.root(), size);
+                // The inertia of a flat plate around its own centre
+                let plate = (size.x as f32).powi(2) + (size.y as f32).powi(2);
+                (block.mass, Vector2::new(center.x, center.y), plate / 12.0)
+            })
+            .collect();
+
+        let mass: f32 = masses.iter().map(|(mass, _, _)| mass).sum();
+        if mass <= 0.0 {
+            return MassProperties {
+                mass: 0.0,
+                center: Vector2::zero(),
+                inertia: 0.0,
+            };
+        }
+
+        let center = masses
+            .iter()
+            .fold(Vector2::zero(), |sum, (mass, pos, _)| sum + pos * *mass)
+            / mass;
+        let inertia = masses
+            .iter()
+            .map(|(mass, pos, plate)| mass * ((pos - center).magnitude2() + plate))
+            .sum();
+
+        MassProperties {
+            mass,
+            center,
+            inertia,
+        }
+    }
+}
+
+/// Fires the engines of the player's ship, and turns every ship around its centre of mass.
+///
+/// Holding Up fires every engine, and Left or Right fire the engines that turn the
+/// ship that way. Engines push away from the direction they face, from wherever they
+/// sit on the ship, so engines that are off-centre turn the ship as well as pushing it.
+pub struct ShipMovementSystem;
+
+impl ShipMovementSystem {
+    /// The smallest turning force (per unit of thrust) that counts as turning the ship
+    const TURN_THRESHOLD: f32 = 0.1;
+}
+
+impl<'a> System<'a> for ShipMovementSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, InputManager>,
+        ReadExpect<'a, PlayerShip>,
+        ReadExpect<'a, Blocks>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        WriteStorage<'a, PowerConsumer>,
+        WriteStorage<'a, ShipBody>,
+        WriteStorage<'a, RigidBody>,
+        WriteStorage<'a, Transform>,
+        ReadStorage<'a, Construction>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            input,
+            player_ship,
+            blocks,
+            ships,
+            block_entities,
+            mut consumers,
+            mut ship_bodies,
+            mut rigid_bodies,
+            mut transforms,
+            constructions,
+        ) = data;
+        let dt = 1.0 / 60.0;
+
+        let thrust = input.keys.is_key_down(VirtualKeyCode::Up);
+        let turn = if input.keys.is_key_down(VirtualKeyCode::Left) {
+            1.0
+        } else if input.keys.is_key_down(VirtualKeyCode::Right) {
+            -1.0
+        } else {
+            0.0
+        };
+
+        for (entity, ship, ship_body, rigid_body, transform) in (
+            &entities,
+            &ships,
+            &mut ship_bodies,
+            &mut rigid_bodies,
+            &mut transforms,
+        )
+            .join()
+        {
+            let mass = MassProperties::of(ship, &blocks, &block_entities);
+
+            // Forces are added up in the ship's own space
+            let mut force = Vector2::zero();
+            let mut torque = 0.0;
+            if entity == player_ship.0 && mass.mass > 0.0 && (thrust || turn != 0.0) {
+                for engine in ship.blocks().into_iter().chain(ship.gadgets()) {
+                    let block_entity = match block_entities.get(engine) {
+                        Some(block_entity) => block_entity,
+                        None => continue,
+                    };
+                    let block = blocks.get_block(block_entity.block_id());
+                    if block.thrust <= 0.0 || constructions.contains(engine) {
+                        continue;
+                    }
+
+                    let size = block_entity.orientation().rotate_size(block.size);
+                    let pos = ship::footprint_center(block_entity.root(), size);
+                    let offset = Vector2::new(pos.x, pos.y) - mass.center;
+                    let push = -block_entity.orientation().direction().truncate();
+                    let turning = offset.perp_dot(push);
+
+                    // While turning, engines that would turn the ship the other way stay off.
+                    // When thrusting, engines that don't turn the ship at all fire too.
+                    let threshold = if thrust {
+                        -Self::TURN_THRESHOLD
+                    } else {
+                        Self::TURN_THRESHOLD
+                    };
+                    if turning * turn <= threshold {
+                        continue;
+                    }
+
+                    let power = consumers.get_mut(engine).map_or(1.0, PowerConsumer::draw);
+                    force += push * block.thrust * power;
+                    torque += turning * block.thrust * power;
+                }
+            }
+
+            if mass.mass > 0.0 {
+                let acceleration = transform.rotation.rotate_vector(force.extend(0.0)) / mass.mass;
+                rigid_body.velocity += acceleration * dt;
+                ship_body.angular_velocity += torque / mass.inertia * dt;
+            }
+            rigid_body.velocity *= 1.0 - ShipBody::LINEAR_DRAG * dt;
+            ship_body.angular_velocity *= 1.0 - ShipBody::ANGULAR_DRAG * dt;
+            if rigid_body.velocity.magnitude() < ShipBody::REST_SPEED {
+                rigid_body.velocity = Zero::zero();
+            }
+            if ship_body.angular_velocity.abs() < ShipBody::REST_SPEED {
+                ship_body.angular_velocity = 0.0;
+            }
+
+            if ship_body.angular_velocity != 0.0 {
+                // Turn around the centre of mass, which stays where it is
+                let center = mass.center.extend(0.0);
+                let pivot = transform.position + transform.rotation.rotate_vector(center);
+                transform.rotation = Quaternion::from_angle_z(Rad(ship_body.angular_velocity * dt))
+                    * transform.rotation;
+                transform.position = pivot - transform.rotation.rotate_vector(center);
+            }
+        }
+    }
+}
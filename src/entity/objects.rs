@@ -1,21 +1,22 @@
 use super::{
-    physics::{Collider, ColliderShape, Hitbox, RigidBody},
-    Model, ToBeRemoved, Transform,
+    hierarchy::{self, Parent},
+    physics::{self, Collider, ColliderShape, CollisionEvent, Hitbox, RigidBody},
+    BlockEntity, Model, ToBeRemoved, Transform,
 };
 use crate::graphics::{MeshId, MeshManager};
 use crate::item::{GameItem, Inventory};
-use cgmath::{prelude::*, Vector3};
-use specs::{prelude::*, world::LazyBuilder, Component};
+use cgmath::{prelude::*, Point3, Vector3};
+use specs::{prelude::*, shrev::EventChannel, world::LazyBuilder, Component};
 use std::collections::HashMap;
 
 /// Stores miscellaneous meshes (these are usually entities)
 pub struct ObjectMeshes {
     pub asteroids: HashMap<GameItem, MeshId>,
-    pub mining_missle: MeshId,
+    pub crew: MeshId,
 }
 
 impl ObjectMeshes {
-    pub fn load(device: &wgpu::Device, mesh_manager: &mut MeshManager) -> ObjectMeshes {
+    pub fn load(mesh_manager: &mut MeshManager) -> ObjectMeshes {
         let asteroid_base = crate::graphics::load_mesh("asteroid");
 
         let asteroids: HashMap<GameItem, MeshId> = GameItem::asteroid_info()
@@ -23,13 +24,18 @@ impl ObjectMeshes {
             .map(|(item, color)| {
                 let mut mesh = asteroid_base.clone();
                 mesh.recolor(*color);
-                (*item, mesh_manager.add(device, &mesh))
+                (*item, mesh_manager.add(&mesh))
             })
             .collect();
 
+        // Crew members are small blue boxes, standing upright on the floor
+        let mut crew = crate::graphics::load_mesh("box");
+        crew.scale(0.3, 0.3, 0.8);
+        crew.tint(Point3::new(0.2, 0.4, 1.0));
+
         Self {
             asteroids,
-            mining_missle: mesh_manager.add(device, &crate::graphics::load_mesh("mining_missle")),
+            crew: mesh_manager.add(&crew),
         }
     }
 }
@@ -37,11 +43,9 @@ impl ObjectMeshes {
 pub fn register_components(world: &mut World) {
     world.register::<Asteroid>();
     world.register::<Health>();
-    world.register::<MiningMissle>();
 }
 
 pub fn setup_systems(builder: &mut DispatcherBuilder) {
-    builder.add(MiningMissleSystem, "", &[]);
     builder.add(NoMoreHealthSystem, "", &[]);
     builder.add(AsteroidShrinkSystem, "", &[]);
 }
@@ -55,6 +59,11 @@ impl Health {
         self.0 -= amount.min(self.0);
     }
 
+    /// Restores health, up to the given maximum
+    pub fn repair(&mut self, amount: u32, max_health: u32) {
+        self.0 = self.0.saturating_add(amount).min(max_health);
+    }
+
     pub fn health(&self) -> u32 {
         self.0
     }
@@ -88,6 +97,36 @@ impl Asteroid {
     pub const HEALTH: u32 = 180;
     pub const COLLIDER_RADIUS: f32 = 0.8;
     pub const VELOCITY: f32 = 1.3;
+    /// The damage dealt by a full sized asteroid hitting a block at a speed of 1
+    pub const IMPACT_DAMAGE: f32 = 30.0;
+
+    /// The damage dealt to a block by an asteroid of the given size (its scale)
+    /// hitting it at the given speed
+    pub fn impact_damage(speed: f32, size: f32) -> u32 {
+        (Self::IMPACT_DAMAGE * speed * size).round() as u32
+    }
+}
+
+pub fn build_asteroid(
+    meshes: &ObjectMeshes,
+    builder: LazyBuilder,
+    item: GameItem,
+    health: u32,
+    transform: Transform,
+    velocity: Vector3<f32>,
+) -> Entity {
+    builder
+        .with(transform)
+        .with(Model::new(*meshes.asteroids.get(&item).unwrap()))
+        .with(RigidBody { velocity })
+        .with(Collider::new(
+            Hitbox::with_shape(ColliderShape::Sphere(Asteroid::COLLIDER_RADIUS)),
+            Collider::ASTEROID,
+            vec![Collider::SHIP, Collider::MISSLE],
+        ))
+        .with(Asteroid(item))
+        .with(Health(health))
+        .build()
 }
 
 pub struct AsteroidShrinkSystem;
@@ -131,81 +170,72 @@ impl<'a> System<'a> for AsteroidMinedSystem {
     }
 }
 
-#[derive(Component)]
-#[storage(HashMapStorage)]
-pub struct MiningMissle {
-    target: Entity,
-}
-
-impl MiningMissle {
-    const SPEED: f32 = 6.5;
+/// Breaks apart asteroids that hit a block, damaging the block they hit
+pub struct AsteroidImpactSystem {
+    collision_reader: ReaderId<CollisionEvent>,
 }
 
-pub fn build_mining_missle(
-    meshes: &ObjectMeshes,
-    builder: LazyBuilder,
-    target: Entity,
-    pos: Vector3<f32>,
-) {
-    builder
-        .with(Transform::from_position(pos.x, pos.y, pos.z))
-        .with(Model::new(meshes.mining_missle))
-        .with(RigidBody {
-            velocity: Vector3::new(0.0, 0.0, MiningMissle::SPEED),
-        })
-        .with(Collider::new(
-            Hitbox::with_shape(ColliderShape::Sphere(0.2)),
-            Collider::MISSLE,
-            vec![Collider::ASTEROID],
-        ))
-        .with(MiningMissle { target })
-        .build();
+impl AsteroidImpactSystem {
+    pub fn new(world: &mut World) -> Self {
+        Self {
+            collision_reader: world
+                .fetch_mut::<EventChannel<CollisionEvent>>()
+                .register_reader(),
+        }
+    }
 }
 
-struct MiningMissleSystem;
-
-impl<'a> System<'a> for MiningMissleSystem {
+impl<'a> System<'a> for AsteroidImpactSystem {
     type SystemData = (
-        Entities<'a>,
+        Read<'a, EventChannel<CollisionEvent>>,
         Write<'a, ToBeRemoved>,
-        ReadStorage<'a, MiningMissle>,
         ReadStorage<'a, Transform>,
-        WriteStorage<'a, RigidBody>,
+        ReadStorage<'a, RigidBody>,
+        ReadStorage<'a, Parent>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Asteroid>,
+        WriteStorage<'a, Health>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, mut to_be_removed, missles, transforms, mut rigid_bodies) = data;
-
-        for (entity, missle) in (&entities, &missles).join() {
-            if !entities.is_alive(missle.target) {
-                to_be_removed.add(entity);
-                continue;
-            }
-
-            let missle_pos = transforms.get(entity).unwrap().position;
-            let target_pos = transforms.get(missle.target).unwrap().position;
-
-            if missle_pos.z >= target_pos.z {
-                rigid_bodies.get_mut(entity).unwrap().velocity = Vector3::new(
-                    target_pos.x - missle_pos.x,
-                    target_pos.y - missle_pos.y,
-                    0.0,
-                )
-                .normalize()
-                    * MiningMissle::SPEED;
-            } else if missle_pos.z >= target_pos.z - 1.0 {
-                let z_factor = 1.0 - (target_pos.z - missle_pos.z).powf(2.0);
-                let mut velocity = Vector3::new(
-                    target_pos.x - missle_pos.x,
-                    target_pos.y - missle_pos.y,
-                    0.0,
-                )
-                .normalize()
-                    * z_factor
-                    * MiningMissle::SPEED;
-                velocity.z = MiningMissle::SPEED * (1.0 - z_factor).max(0.4);
-                rigid_bodies.get_mut(entity).unwrap().velocity = velocity;
+        let (
+            collisions,
+            mut to_be_removed,
+            transforms,
+            bodies,
+            parents,
+            blocks,
+            asteroids,
+            mut healths,
+        ) = data;
+
+        for event in collisions.read(&mut self.collision_reader) {
+            let pair = match event {
+                CollisionEvent::Started(a, b) => (*a, *b),
+                CollisionEvent::Stopped(_, _) => continue,
+            };
+            let (block, asteroid) = match physics::match_pair(
+                pair,
+                |entity| blocks.contains(entity),
+                |entity| asteroids.contains(entity),
+            ) {
+                Some(pair) => pair,
+                None => continue,
+            };
+
+            // Blocks move with the ship that they are part of
+            let velocity = |entity| {
+                bodies
+                    .get(entity)
+                    .map_or(Vector3::zero(), |body| body.velocity)
+            };
+            let speed =
+                (velocity(asteroid) - velocity(hierarchy::root(&parents, block))).magnitude();
+            let size = transforms.get(asteroid).unwrap().scale.x;
+            if let Some(health) = healths.get_mut(block) {
+                health.damage(Asteroid::impact_damage(speed, size));
             }
+            to_be_removed.add(asteroid);
         }
     }
 }
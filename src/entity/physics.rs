@@ -1,4 +1,4 @@
-use super::{SimpleStorage, ToBeRemoved, Transform};
+use super::{ToBeRemoved, Transform};
 use crate::graphics::{Mesh, MeshId, MeshManager, ModelId, Vertex};
 use cgmath::{prelude::*, Matrix4, Point3, Vector3};
 use nalgebra::{
@@ -8,12 +8,14 @@ use nalgebra::{
 };
 use ncollide3d::{
     pipeline::narrow_phase::ContactEvent,
-    pipeline::object::{CollisionGroups, CollisionObjectSlabHandle},
+    pipeline::object::{CollisionGroups, CollisionObjectSlabHandle, GeometricQueryType},
     query::Ray,
     shape,
     world::CollisionWorld,
 };
-use specs::{prelude::*, Component};
+use serde::{Deserialize, Serialize};
+use specs::{prelude::*, shrev::EventChannel, storage::ComponentEvent, world::Index, Component};
+use std::collections::HashMap;
 
 #[derive(Component)]
 #[storage(VecStorage)]
@@ -21,92 +23,162 @@ pub struct RigidBody {
     pub velocity: Vector3<f32>,
 }
 
-pub struct PhysicsSystem;
+/// Moves everything with a rigid body along its velocity
+pub struct RigidBodySystem;
 
-impl<'a> System<'a> for PhysicsSystem {
-    type SystemData = (
-        Entities<'a>,
-        Write<'a, ToBeRemoved>,
-        WriteStorage<'a, Transform>,
-        ReadStorage<'a, Collider>,
-        ReadStorage<'a, RigidBody>,
-        ReadStorage<'a, super::BlockEntity>,
-        ReadStorage<'a, super::objects::Asteroid>,
-        ReadStorage<'a, super::objects::MiningMissle>,
-    );
+impl<'a> System<'a> for RigidBodySystem {
+    type SystemData = (WriteStorage<'a, Transform>, ReadStorage<'a, RigidBody>);
 
     fn run(&mut self, data: Self::SystemData) {
-        let (
-            entities,
-            mut to_be_removed,
-            mut transforms,
-            colliders,
-            bodies,
-            blocks,
-            asteroids,
-            missles,
-        ) = data;
-        let mut world: CollisionWorld<f32, Entity> = CollisionWorld::new(0.02);
+        let (mut transforms, bodies) = data;
         let dt = 1.0 / 60.0;
-        let contact_query = ncollide3d::pipeline::object::GeometricQueryType::Contacts(0.0, 0.0);
 
-        // Update Rigid Bodies
         for (transform, body) in (&mut transforms, &bodies).join() {
             transform.position += body.velocity * dt;
         }
+    }
+}
+
+/// Published by the `PhysicsSystem` whenever two colliders start or stop touching.
+/// Gameplay systems read these from the `EventChannel<CollisionEvent>` resource and decide what
+/// the collision does, so the physics itself knows nothing about blocks, asteroids or projectiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionEvent {
+    Started(Entity, Entity),
+    /// Either entity may no longer be alive
+    Stopped(Entity, Entity),
+}
+
+/// Orders the pair of colliding entities so that the first is accepted by `first`, and the
+/// second by `second`. Returns `None` when neither order fits.
+pub fn match_pair(
+    (a, b): (Entity, Entity),
+    first: impl Fn(Entity) -> bool,
+    second: impl Fn(Entity) -> bool,
+) -> Option<(Entity, Entity)> {
+    if first(a) && second(b) {
+        Some((a, b))
+    } else if first(b) && second(a) {
+        Some((b, a))
+    } else {
+        None
+    }
+}
+
+/// Keeps the `PhysicsWorld` up to date with the colliders that were added, changed, moved or
+/// removed since the last update, then publishes a `CollisionEvent` for every pair of colliders
+/// that started or stopped touching.
+pub struct PhysicsSystem {
+    changes: ColliderChanges,
+}
+
+impl PhysicsSystem {
+    pub fn new(world: &mut World) -> Self {
+        Self {
+            changes: ColliderChanges::new(world),
+        }
+    }
+}
+
+impl<'a> System<'a> for PhysicsSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteExpect<'a, PhysicsWorld>,
+        Write<'a, EventChannel<CollisionEvent>>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Collider>,
+    );
 
-        // Setup Collision
-        for (entity, transform, collider) in (&entities, &transforms, &colliders).join() {
-            let position = to_nalgebra_pos(&transform, &collider.hitbox.offset);
-            let shape = collider.hitbox.as_shape_handle();
-            let mut group = CollisionGroups::new()
-                .with_membership(&[collider.group])
-                .with_whitelist(&collider.whitelist);
-            group.disable_self_interaction();
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut world, mut collisions, transforms, colliders) = data;
+        let changes = &mut self.changes;
+        changes.read(&colliders, &transforms);
 
-            world.add(position, shape, group, contact_query, entity);
+        for id in &changes.removed {
+            world.remove(*id, &mut collisions);
+        }
+        for (entity, collider, transform, _) in
+            (&entities, &colliders, &transforms, &changes.changed).join()
+        {
+            world.insert(entity, collider, transform);
         }
+        for (entity, collider, transform, _, _) in (
+            &entities,
+            &colliders,
+            &transforms,
+            &changes.moved,
+            !&changes.changed,
+        )
+            .join()
+        {
+            world.set_position(entity, collider, transform);
+        }
+
         // crate::print_time("PhysicsStart");
         world.update();
         // crate::print_time("PhysicsEnd");
 
-        //Process Collisions
-        fn has_component<T: Component>(
-            e1: Entity,
-            e2: Entity,
-            component: &SimpleStorage<'_, T>,
-        ) -> bool {
-            component.contains(e1) || component.contains(e2)
+        let world = &world.world;
+        let entity = |handle| *world.collision_object(handle).unwrap().data();
+        collisions.iter_write(world.contact_events().iter().map(|event| match *event {
+            ContactEvent::Started(h1, h2) => CollisionEvent::Started(entity(h1), entity(h2)),
+            ContactEvent::Stopped(h1, h2) => CollisionEvent::Stopped(entity(h1), entity(h2)),
+        }));
+    }
+}
+
+/// Reads the changes to colliders, and to the transforms of the entities that have them
+struct ColliderChanges {
+    collider_reader: ReaderId<ComponentEvent>,
+    transform_reader: ReaderId<ComponentEvent>,
+    /// Colliders that were added or changed
+    changed: BitSet,
+    /// Entities that moved, whose collider may not have changed otherwise
+    moved: BitSet,
+    /// Colliders that were removed. These are handled before the changed colliders,
+    /// since a new entity can take the place of a removed one.
+    removed: Vec<Index>,
+}
+
+impl ColliderChanges {
+    fn new(world: &mut World) -> Self {
+        Self {
+            collider_reader: world.write_storage::<Collider>().register_reader(),
+            transform_reader: world.write_storage::<Transform>().register_reader(),
+            changed: BitSet::new(),
+            moved: BitSet::new(),
+            removed: Vec::new(),
         }
+    }
+
+    fn read(&mut self, colliders: &ReadStorage<Collider>, transforms: &ReadStorage<Transform>) {
+        self.changed.clear();
+        self.moved.clear();
+        self.removed.clear();
 
-        for event in world.contact_events() {
+        for event in colliders.channel().read(&mut self.collider_reader) {
             match event {
-                ContactEvent::Started(h1, h2) => {
-                    let entity1 = *world.collision_object(*h1).unwrap().data();
-                    let entity2 = *world.collision_object(*h2).unwrap().data();
-
-                    if has_component(entity1, entity2, &blocks) {
-                        if asteroids.contains(entity1) {
-                            to_be_removed.add(entity1);
-                        } else if asteroids.contains(entity2) {
-                            to_be_removed.add(entity2);
-                        }
-                    }
-
-                    if has_component(entity1, entity2, &missles)
-                        && has_component(entity1, entity2, &asteroids)
-                    {
-                        to_be_removed.add(entity1);
-                        to_be_removed.add(entity2);
-                    }
+                ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => {
+                    self.changed.add(*id);
+                }
+                ComponentEvent::Removed(id) => {
+                    self.changed.remove(*id);
+                    self.removed.push(*id);
                 }
-                ContactEvent::Stopped(_, _) => {}
+            }
+        }
+
+        for event in transforms.channel().read(&mut self.transform_reader) {
+            if let ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) = event {
+                self.moved.add(*id);
             }
         }
     }
 }
 
 fn to_nalgebra_pos(transform: &Transform, offset: &Vector3<f32>) -> Isometry3<f32> {
+    // The offset turns along with the entity
+    let offset = transform.rotation.rotate_vector(*offset);
     let translation = Translation3::new(
         transform.position.x + offset.x,
         transform.position.y + offset.y,
@@ -155,7 +227,7 @@ impl Hitbox {
 
     pub fn to_hitbox_model(&self, transform: &Transform) -> Matrix4<f32> {
         let mut hb_transform = transform.clone();
-        hb_transform.position += self.offset;
+        hb_transform.position += transform.rotation.rotate_vector(self.offset);
 
         match self.shape {
             ColliderShape::Cuboid(size) => hb_transform.scale = size,
@@ -179,27 +251,24 @@ impl Hitbox {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum ColliderShape {
     /// The Full Size of the Box
     Cuboid(Vector3<f32>),
     Sphere(f32),
 }
 
-#[derive(Component)]
-#[storage(VecStorage)]
 pub struct Collider {
     pub hitbox: Hitbox,
     pub group: usize,
     pub whitelist: Vec<usize>,
-    raycast_id: Option<CollisionObjectSlabHandle>,
-    model_id: Option<ModelId>,
+}
+
+impl Component for Collider {
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
 }
 
 impl Collider {
-    /// Internal Use Only. Used to prevent Raycast colliders
-    /// from self interacting.
-    const RAYCAST: usize = 0;
     pub const ASTEROID: usize = 1;
     pub const SHIP: usize = 2;
     pub const MISSLE: usize = 3;
@@ -209,17 +278,101 @@ impl Collider {
             hitbox,
             group,
             whitelist,
-            raycast_id: None,
-            model_id: None,
         }
     }
+
+    fn collision_groups(&self) -> CollisionGroups {
+        let mut groups = CollisionGroups::new()
+            .with_membership(&[self.group])
+            .with_whitelist(&self.whitelist);
+        groups.disable_self_interaction();
+        groups
+    }
 }
 
-pub struct RaycastWorld(CollisionWorld<f32, Entity>);
+/// The collision world that every collider is kept in, which finds the contacts between them
+/// and answers raycasts. Colliders are added, moved and removed as they change, rather than
+/// the world being built again every update.
+pub struct PhysicsWorld {
+    world: CollisionWorld<f32, Entity>,
+    /// The collision object of every entity with a collider
+    handles: HashMap<Index, CollisionObjectSlabHandle>,
+}
 
-impl RaycastWorld {
+impl PhysicsWorld {
     pub fn new() -> Self {
-        Self(CollisionWorld::new(0.02))
+        Self {
+            world: CollisionWorld::new(0.02),
+            handles: HashMap::new(),
+        }
+    }
+
+    /// The number of colliders in the world
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Adds the entity's collider to the world, or updates it if it is already there
+    pub fn insert(&mut self, entity: Entity, collider: &Collider, transform: &Transform) {
+        let position = to_nalgebra_pos(transform, &collider.hitbox.offset);
+        let shape = collider.hitbox.as_shape_handle();
+
+        let world = &mut self.world;
+        if let Some(object) = self
+            .handles
+            .get(&entity.id())
+            .and_then(|handle| world.get_mut(*handle))
+        {
+            object.set_position(position);
+            object.set_shape(shape);
+            object.set_collision_groups(collider.collision_groups());
+        } else {
+            let contact_query = GeometricQueryType::Contacts(0.0, 0.0);
+            let (handle, _) = world.add(
+                position,
+                shape,
+                collider.collision_groups(),
+                contact_query,
+                entity,
+            );
+            self.handles.insert(entity.id(), handle);
+        }
+    }
+
+    /// Moves the entity's collider along with its transform
+    pub fn set_position(&mut self, entity: Entity, collider: &Collider, transform: &Transform) {
+        let world = &mut self.world;
+        if let Some(object) = self
+            .handles
+            .get(&entity.id())
+            .and_then(|handle| world.get_mut(*handle))
+        {
+            object.set_position(to_nalgebra_pos(transform, &collider.hitbox.offset));
+        }
+    }
+
+    /// Removes the collider of the entity with the given id, if it is in the world.
+    /// Everything that it was touching stops touching it.
+    pub fn remove(&mut self, id: Index, collisions: &mut EventChannel<CollisionEvent>) {
+        let handle = match self.handles.remove(&id) {
+            Some(handle) => handle,
+            None => return,
+        };
+        let world = &self.world;
+        let entity = |handle| *world.collision_object(handle).unwrap().data();
+        if let Some(touching) = world.collision_objects_in_contact_with(handle) {
+            collisions.iter_write(
+                touching
+                    .map(|other| CollisionEvent::Stopped(entity(handle), entity(other)))
+                    .collect::<Vec<_>>(),
+            );
+        }
+        self.world.remove(&[handle]);
+    }
+
+    /// Finds the contacts between the colliders where they are now
+    pub fn update(&mut self) {
+        self.world.update();
     }
 
     /// Note: If the whitelist is empty,
@@ -240,89 +393,81 @@ impl RaycastWorld {
             groups.set_whitelist(&whitelist);
         }
 
-        self.0
+        self.world
             .first_interference_with_ray(&ray, toi, &groups)
             .map(|result| *result.co.data())
     }
 }
 
-pub struct RaycastSystem;
+/// Takes the colliders of removed entities out of the `PhysicsWorld` straight away,
+/// so that nothing can hit them before the next physics update
+pub struct RemoveColliderSystem;
 
-impl<'a> System<'a> for RaycastSystem {
+impl<'a> System<'a> for RemoveColliderSystem {
     type SystemData = (
         Entities<'a>,
-        WriteExpect<'a, RaycastWorld>,
-        ReadStorage<'a, Transform>,
-        WriteStorage<'a, Collider>,
-        WriteExpect<'a, MeshManager>,
-        ReadExpect<'a, HitboxMeshes>,
+        Read<'a, ToBeRemoved>,
+        WriteExpect<'a, PhysicsWorld>,
+        Write<'a, EventChannel<CollisionEvent>>,
+        ReadStorage<'a, Collider>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, mut world, transforms, mut colliders, mut meshes, hitbox_meshes) = data;
-        let world = &mut world.0;
-        let contact_query = ncollide3d::pipeline::object::GeometricQueryType::Contacts(0.8, 0.8);
+        let (entities, to_be_removed, mut world, mut collisions, colliders) = data;
 
-        for (entity, transform, mut colliders) in
-            (&entities, &transforms, &mut colliders.restrict_mut()).join()
-        {
-            // Safety: We can use get_unchecked here because we know the entity is alive
-            // from joining the Entities resource
-            let collider = colliders.get_unchecked();
-            let position = to_nalgebra_pos(&transform, &collider.hitbox.offset);
-            let hitbox_mesh = collider.hitbox.to_hitbox_mesh(&hitbox_meshes);
-            let hitbox_matrix = collider.hitbox.to_hitbox_model(&transform);
-
-            if let (Some(id), Some(model)) = (collider.raycast_id, collider.model_id) {
-                let collider_object = world
-                    .get_mut(id)
-                    .expect("Raycast ID does not exist in collision world!");
-                collider_object.set_position(position);
-                collider_object.set_shape(collider.hitbox.as_shape_handle());
-                meshes.update_model(hitbox_mesh, model, hitbox_matrix);
-            } else {
-                let collider = colliders.get_mut_unchecked();
-                let shape = collider.hitbox.as_shape_handle();
-                let group = ncollide3d::pipeline::object::CollisionGroups::new()
-                    .with_membership(&[collider.group])
-                    .with_whitelist(&[Collider::RAYCAST]);
-
-                collider.raycast_id =
-                    Some(world.add(position, shape, group, contact_query, entity).0);
-
-                // TODO: Rendering happens in the raycast update system? This either should be renamed
-                // or needs to happen in a different system.
-                collider.model_id = Some(meshes.new_model(hitbox_mesh, hitbox_matrix));
-            }
+        for (entity, _, _) in (&entities, &colliders, to_be_removed.bitset()).join() {
+            world.remove(entity.id(), &mut collisions);
         }
-        world.update();
     }
 }
 
-pub struct RemoveRaycastColliderSystem;
+/// Shows the hitbox of every collider, when `RENDER_HITBOXES` is turned on
+pub struct HitboxModelSystem {
+    changes: ColliderChanges,
+    /// The model of every collider's hitbox, along with the mesh it was created for
+    models: HashMap<Index, (MeshId, ModelId)>,
+}
 
-impl<'a> System<'a> for RemoveRaycastColliderSystem {
+impl HitboxModelSystem {
+    pub fn new(world: &mut World) -> Self {
+        Self {
+            changes: ColliderChanges::new(world),
+            models: HashMap::new(),
+        }
+    }
+}
+
+impl<'a> System<'a> for HitboxModelSystem {
     type SystemData = (
-        Read<'a, ToBeRemoved>,
-        WriteExpect<'a, RaycastWorld>,
         WriteExpect<'a, MeshManager>,
         ReadExpect<'a, HitboxMeshes>,
-        WriteStorage<'a, Collider>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Collider>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (to_be_removed, mut raycast_world, mut mesh_manager, hitbox_meshes, mut colliders) =
-            data;
+        let (mut meshes, hitbox_meshes, transforms, colliders) = data;
+        let changes = &mut self.changes;
+        changes.read(&colliders, &transforms);
 
-        for (collider, _) in (&mut colliders, to_be_removed.bitset()).join() {
-            if let Some(id) = collider.raycast_id {
-                raycast_world.0.remove(&[id]);
-                collider.raycast_id = None;
+        for id in &changes.removed {
+            if let Some((mesh, model)) = self.models.remove(id) {
+                meshes.remove_model(mesh, model);
             }
-
-            if let Some(id) = collider.model_id {
-                mesh_manager.remove_model(collider.hitbox.to_hitbox_mesh(&hitbox_meshes), id);
-                collider.model_id = None;
+        }
+        for (collider, transform, id) in (&colliders, &transforms, &changes.changed).join() {
+            if let Some((mesh, model)) = self.models.remove(&id) {
+                meshes.remove_model(mesh, model);
+            }
+            let mesh = collider.hitbox.to_hitbox_mesh(&hitbox_meshes);
+            let model = meshes.new_model(mesh, collider.hitbox.to_hitbox_model(transform));
+            self.models.insert(id, (mesh, model));
+        }
+        for (collider, transform, id, _) in
+            (&colliders, &transforms, &changes.moved, !&changes.changed).join()
+        {
+            if let Some((mesh, model)) = self.models.get(&id) {
+                meshes.update_model(*mesh, *model, collider.hitbox.to_hitbox_model(transform));
             }
         }
     }
@@ -334,9 +479,9 @@ pub struct HitboxMeshes {
 }
 
 impl HitboxMeshes {
-    pub fn load(device: &wgpu::Device, mesh_manager: &mut MeshManager) -> Self {
+    pub fn load(mesh_manager: &mut MeshManager) -> Self {
         let mut register_mesh = |mesh: &Mesh| {
-            let id = mesh_manager.add(device, mesh);
+            let id = mesh_manager.add(mesh);
             mesh_manager.set_mesh_visisble(id, crate::RENDER_HITBOXES);
             id
         };
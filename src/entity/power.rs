@@ -0,0 +1,154 @@
+use super::{jobs::Construction, BlockEntity, PlayerShip, Ship};
+use crate::block::Blocks;
+use specs::{prelude::*, Component};
+
+pub fn register_components(world: &mut World) {
+    world.register::<PowerConsumer>();
+    world.register::<Battery>();
+}
+
+/// A block that needs power to work, such as a laser or a miner
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct PowerConsumer {
+    /// Whether the block drew power during this update
+    active: bool,
+    /// How much of the block's demand its grid could meet (0 to 1)
+    satisfaction: f32,
+}
+
+impl PowerConsumer {
+    /// Draws the block's power for this update. Returns how much of it the
+    /// grid was able to supply, where 1 is all of it and 0 is none.
+    pub fn draw(&mut self) -> f32 {
+        self.active = true;
+        self.satisfaction
+    }
+}
+
+/// A block that stores power that its grid didn't use
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct Battery {
+    pub charge: f32,
+}
+
+/// The balance of every power grid on the player's ship, shown by the UI
+#[derive(Default)]
+pub struct PowerStatus {
+    /// Power generated per second
+    pub generation: f32,
+    /// Power drawn by consumers per second
+    pub demand: f32,
+    pub stored: f32,
+    pub capacity: f32,
+}
+
+/// Balances each grid's generation against the demand of its consumers, using
+/// batteries to cover shortfalls. Consumers are throttled by the share of their
+/// demand that can be met, so this runs after they have drawn power.
+pub struct PowerSystem;
+
+impl<'a> System<'a> for PowerSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Blocks>,
+        ReadExpect<'a, PlayerShip>,
+        WriteExpect<'a, PowerStatus>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        WriteStorage<'a, PowerConsumer>,
+        WriteStorage<'a, Battery>,
+        ReadStorage<'a, Construction>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            blocks,
+            player_ship,
+            mut power_status,
+            ships,
+            block_entities,
+            mut consumers,
+            mut batteries,
+            constructions,
+        ) = data;
+        let dt = 1.0 / 60.0;
+
+        for (ship_entity, ship) in (&entities, &ships).join() {
+            let mut status = PowerStatus::default();
+
+            for grid in ship.power_grids() {
+                let mut generation = 0.0;
+                let mut demand = 0.0;
+                let mut stored = 0.0;
+                let mut capacity = 0.0;
+                for entity in &grid {
+                    // Blocks that haven't been built yet are left out of their grid
+                    if constructions.contains(*entity) {
+                        continue;
+                    }
+                    let block = match block_entities.get(*entity) {
+                        Some(block_entity) => blocks.get_block(block_entity.block_id()),
+                        None => continue,
+                    };
+
+                    generation += block.power_generation;
+                    if consumers
+                        .get(*entity)
+                        .is_some_and(|consumer| consumer.active)
+                    {
+                        demand += block.power_usage;
+                    }
+                    if let Some(battery) = batteries.get(*entity) {
+                        stored += battery.charge;
+                        capacity += block.battery_capacity;
+                    }
+                }
+
+                // Surplus power charges the batteries, and they cover any shortfall
+                let supply = generation * dt;
+                let needed = demand * dt;
+                let satisfaction = if needed > 0.0 {
+                    ((supply + stored) / needed).min(1.0)
+                } else if supply + stored > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                };
+                let new_stored = (stored + supply - needed).max(0.0).min(capacity);
+
+                // Every battery on the grid is kept at the same level
+                let level = if capacity > 0.0 {
+                    new_stored / capacity
+                } else {
+                    0.0
+                };
+                for entity in &grid {
+                    // Unbuilt batteries don't take any of the grid's charge
+                    if constructions.contains(*entity) {
+                        continue;
+                    }
+                    if let Some(consumer) = consumers.get_mut(*entity) {
+                        consumer.satisfaction = satisfaction;
+                        consumer.active = false;
+                    }
+                    if let Some(battery) = batteries.get_mut(*entity) {
+                        let block_id = block_entities.get(*entity).unwrap().block_id();
+                        battery.charge = blocks.get_block(block_id).battery_capacity * level;
+                    }
+                }
+
+                status.generation += generation;
+                status.demand += demand;
+                status.stored += new_stored;
+                status.capacity += capacity;
+            }
+
+            if ship_entity == player_ship.0 {
+                *power_status = status;
+            }
+        }
+    }
+}
@@ -0,0 +1,443 @@
+use super::{
+    objects::{Asteroid, Health},
+    physics::{self, Collider, ColliderShape, CollisionEvent, Hitbox, RigidBody},
+    Model, ToBeRemoved, Transform,
+};
+use crate::graphics::{self, MeshId, MeshManager};
+use crate::item::Inventory;
+use cgmath::{prelude::*, Vector3};
+use serde::{Deserialize, Serialize};
+use specs::{prelude::*, shrev::EventChannel, world::LazyBuilder, Component};
+
+pub fn register_components(world: &mut World) {
+    world.register::<Projectile>();
+    world.register::<Guidance>();
+}
+
+pub fn setup_systems(builder: &mut DispatcherBuilder) {
+    builder.add(ProjectileSystem, "", &[]);
+}
+
+pub const PROJECTILES_PATH: &str = "assets/projectiles.ron";
+
+/// The missles launched by miners, which steer into an asteroid and break it apart
+pub const MINING_MISSLE: &str = "mining missle";
+
+/// The collider groups that projectile definitions can refer to by name
+const COLLIDER_GROUPS: &[(&str, usize)] = &[
+    ("asteroid", Collider::ASTEROID),
+    ("ship", Collider::SHIP),
+    ("missle", Collider::MISSLE),
+];
+
+/// Everything needed to launch a kind of projectile.
+/// New weapons only need a new definition in the projectiles file.
+pub struct ProjectileDefinition {
+    pub name: String,
+    pub mesh_id: MeshId,
+    pub speed: f32,
+    pub lifetime: f32,
+    pub guidance: GuidanceMode,
+    pub turn_rate: f32,
+    /// The shape of the projectile's collider
+    pub shape: ColliderShape,
+    /// The collider groups that the projectile can hit
+    pub hits: Vec<usize>,
+    /// The acceleration that ballistic projectiles fall with
+    pub gravity: Vector3<f32>,
+    pub payload: Payload,
+}
+
+/// A projectile as it is written in the projectiles file
+#[derive(Deserialize)]
+struct ProjectileEntry {
+    name: String,
+    mesh: String,
+    speed: f32,
+    lifetime: f32,
+    guidance: GuidanceMode,
+    #[serde(default)]
+    turn_rate: f32,
+    shape: ColliderShape,
+    hits: Vec<String>,
+    #[serde(default)]
+    gravity: (f32, f32, f32),
+    payload: Payload,
+}
+
+pub struct Projectiles {
+    definitions: Vec<ProjectileDefinition>,
+}
+
+impl Projectiles {
+    pub fn get(&self, name: &str) -> Option<&ProjectileDefinition> {
+        self.definitions
+            .iter()
+            .find(|definition| definition.name == name)
+    }
+}
+
+/// Loads every projectile from the projectiles file. Panics if the file is missing or invalid,
+/// like the blocks file.
+pub fn load_projectiles(mesh_manager: &mut MeshManager) -> Projectiles {
+    let text = std::fs::read_to_string(PROJECTILES_PATH)
+        .unwrap_or_else(|err| panic!("Unable to load {}: {}", PROJECTILES_PATH, err));
+    let entries: Vec<ProjectileEntry> = ron::de::from_str(&text)
+        .unwrap_or_else(|err| panic!("Invalid projectile file {}: {}", PROJECTILES_PATH, err));
+
+    let mut definitions: Vec<ProjectileDefinition> = Vec::new();
+    for entry in entries {
+        if definitions
+            .iter()
+            .any(|definition| definition.name == entry.name)
+        {
+            panic!("Projectile {} is defined more than once", entry.name);
+        }
+        let hits = entry
+            .hits
+            .iter()
+            .map(|name| {
+                COLLIDER_GROUPS
+                    .iter()
+                    .find(|(group, _)| group == name)
+                    .map(|(_, group)| *group)
+                    .unwrap_or_else(|| {
+                        panic!("Projectile {} hits unknown group: {}", entry.name, name)
+                    })
+            })
+            .collect();
+
+        definitions.push(ProjectileDefinition {
+            mesh_id: mesh_manager.add(&graphics::load_mesh(&entry.mesh)),
+            name: entry.name,
+            speed: entry.speed,
+            lifetime: entry.lifetime,
+            guidance: entry.guidance,
+            turn_rate: entry.turn_rate,
+            shape: entry.shape,
+            hits,
+            gravity: entry.gravity.into(),
+            payload: entry.payload,
+        });
+    }
+
+    Projectiles { definitions }
+}
+
+/// The effect of a projectile on whatever it hits
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Payload {
+    /// Damage dealt to whatever the projectile hits
+    pub damage: u32,
+    /// Ore collected straight away from an asteroid that the projectile hits
+    pub ore: u32,
+    /// Damage dealt around the impact, as well as to whatever was hit
+    pub blast: Option<Blast>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Blast {
+    pub radius: f32,
+    /// Damage dealt to everything within the radius that the projectile could have hit
+    pub damage: u32,
+}
+
+/// Something flying through space that delivers its payload to the first thing it hits.
+/// Projectiles fly with their `RigidBody`, and are steered by their `Guidance`.
+#[derive(Component, Clone, Serialize, Deserialize)]
+#[storage(HashMapStorage)]
+pub struct Projectile {
+    /// The name of the projectile's definition
+    #[serde(default = "default_kind")]
+    pub kind: String,
+    /// Units per second
+    pub speed: f32,
+    /// Seconds left until the projectile fizzles out
+    pub lifetime: f32,
+    pub payload: Payload,
+}
+
+/// Older saves only had mining missles
+fn default_kind() -> String {
+    MINING_MISSLE.to_string()
+}
+
+/// How a projectile steers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GuidanceMode {
+    /// Flies on in the direction it was launched
+    Straight,
+    /// Steers towards the target
+    Homing,
+    /// Steers towards where the target will be by the time the projectile gets there
+    LeadPursuit,
+    /// Falls in an arc with the gravity of its definition, without steering
+    Ballistic,
+}
+
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct Guidance {
+    pub mode: GuidanceMode,
+    /// Radians per second
+    pub turn_rate: f32,
+    /// Projectiles without a target (or whose target is gone) fly straight on
+    pub target: Option<Entity>,
+    /// The acceleration that ballistic projectiles fall with
+    pub gravity: Vector3<f32>,
+}
+
+/// Launches a projectile from the position, heading in the direction
+pub fn launch_projectile(
+    definition: &ProjectileDefinition,
+    builder: LazyBuilder,
+    target: Option<Entity>,
+    pos: Vector3<f32>,
+    direction: Vector3<f32>,
+) -> Entity {
+    builder
+        .with(Transform::from_position(pos.x, pos.y, pos.z))
+        .with(Model::new(definition.mesh_id))
+        .with(RigidBody {
+            velocity: direction.normalize() * definition.speed,
+        })
+        .with(Collider::new(
+            Hitbox::with_shape(definition.shape),
+            Collider::MISSLE,
+            definition.hits.clone(),
+        ))
+        .with(Projectile {
+            kind: definition.name.clone(),
+            speed: definition.speed,
+            lifetime: definition.lifetime,
+            payload: definition.payload,
+        })
+        .with(Guidance {
+            mode: definition.guidance,
+            turn_rate: definition.turn_rate,
+            target,
+            gravity: definition.gravity,
+        })
+        .build()
+}
+
+/// Turns the direction towards the desired one, by no more than the angle.
+/// Both directions must be normalized.
+fn turn_towards(direction: Vector3<f32>, desired: Vector3<f32>, max_angle: f32) -> Vector3<f32> {
+    if direction.angle(desired).0 <= max_angle {
+        return desired;
+    }
+
+    // Turn within the plane of the two directions, or any plane when they are opposite each other
+    let mut side = desired - direction * direction.dot(desired);
+    if side.magnitude2() < 1e-6 {
+        let axis = if direction.z.abs() < 0.9 {
+            Vector3::unit_z()
+        } else {
+            Vector3::unit_x()
+        };
+        side = direction.cross(axis);
+    }
+    direction * max_angle.cos() + side.normalize() * max_angle.sin()
+}
+
+/// Steers every projectile with its guidance, and removes the ones that have run out of time
+pub struct ProjectileSystem;
+
+impl<'a> System<'a> for ProjectileSystem {
+    type SystemData = (
+        Entities<'a>,
+        Write<'a, ToBeRemoved>,
+        WriteStorage<'a, Projectile>,
+        ReadStorage<'a, Guidance>,
+        ReadStorage<'a, Transform>,
+        WriteStorage<'a, RigidBody>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut to_be_removed, mut projectiles, guidances, transforms, mut bodies) =
+            data;
+        let dt = 1.0 / 60.0;
+
+        for (entity, projectile, guidance, transform) in
+            (&entities, &mut projectiles, guidances.maybe(), &transforms).join()
+        {
+            projectile.lifetime -= dt;
+            if projectile.lifetime <= 0.0 {
+                to_be_removed.add(entity);
+                continue;
+            }
+            let guidance = match guidance {
+                Some(guidance) => guidance,
+                None => continue,
+            };
+
+            let target = guidance
+                .target
+                .filter(|target| entities.is_alive(*target))
+                .and_then(|target| transforms.get(target).map(|t| (target, t.position)));
+
+            let aim = match (guidance.mode, target) {
+                (GuidanceMode::Homing, Some((_, target_pos))) => target_pos,
+                (GuidanceMode::LeadPursuit, Some((target, target_pos))) => {
+                    // Aim ahead of the target by however long it would take to get to it
+                    let time = (target_pos - transform.position).magnitude() / projectile.speed;
+                    let velocity = bodies
+                        .get(target)
+                        .map_or(Vector3::zero(), |body| body.velocity);
+                    target_pos + velocity * time
+                }
+                (GuidanceMode::Ballistic, _) => {
+                    if let Some(body) = bodies.get_mut(entity) {
+                        body.velocity += guidance.gravity * dt;
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let body = match bodies.get_mut(entity) {
+                Some(body) => body,
+                None => continue,
+            };
+            let offset = aim - transform.position;
+            if offset.magnitude2() == 0.0 || body.velocity.magnitude2() == 0.0 {
+                continue;
+            }
+            let direction = turn_towards(
+                body.velocity.normalize(),
+                offset.normalize(),
+                guidance.turn_rate * dt,
+            );
+            body.velocity = direction * projectile.speed;
+        }
+    }
+}
+
+/// Delivers the payload of each projectile to the first thing it hits
+pub struct ProjectileImpactSystem {
+    collision_reader: ReaderId<CollisionEvent>,
+}
+
+impl ProjectileImpactSystem {
+    pub fn new(world: &mut World) -> Self {
+        Self {
+            collision_reader: world
+                .fetch_mut::<EventChannel<CollisionEvent>>()
+                .register_reader(),
+        }
+    }
+}
+
+impl<'a> System<'a> for ProjectileImpactSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, EventChannel<CollisionEvent>>,
+        Write<'a, ToBeRemoved>,
+        WriteExpect<'a, Inventory>,
+        ReadStorage<'a, Projectile>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Collider>,
+        ReadStorage<'a, Asteroid>,
+        WriteStorage<'a, Health>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            collisions,
+            mut to_be_removed,
+            mut inventory,
+            projectiles,
+            transforms,
+            colliders,
+            asteroids,
+            mut healths,
+        ) = data;
+
+        for event in collisions.read(&mut self.collision_reader) {
+            let pair = match event {
+                CollisionEvent::Started(a, b) => (*a, *b),
+                CollisionEvent::Stopped(_, _) => continue,
+            };
+            let (projectile, hit) =
+                match physics::match_pair(pair, |entity| projectiles.contains(entity), |_| true) {
+                    Some(pair) => pair,
+                    None => continue,
+                };
+            if to_be_removed.bitset().contains(projectile.id()) {
+                continue;
+            }
+
+            let payload = projectiles.get(projectile).unwrap().payload;
+            if let Some(health) = healths.get_mut(hit) {
+                health.damage(payload.damage);
+            }
+            if let Some(asteroid) = asteroids.get(hit) {
+                inventory.change_amount(asteroid.0, payload.ore);
+            }
+
+            // Blasts damage everything nearby that the projectile could have hit
+            if let Some(blast) = payload.blast {
+                let center = transforms.get(projectile).unwrap().position;
+                let whitelist = &colliders.get(projectile).unwrap().whitelist;
+                for (other, transform, collider, health) in
+                    (&entities, &transforms, &colliders, &mut healths).join()
+                {
+                    if other != hit
+                        && whitelist.contains(&collider.group)
+                        && (transform.position - center).magnitude() <= blast.radius
+                    {
+                        health.damage(blast.damage);
+                    }
+                }
+            }
+            to_be_removed.add(projectile);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vector3<f32>, expected: Vector3<f32>) {
+        assert!(
+            (actual - expected).magnitude() < 1e-5,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn turn_towards_reaches_a_close_direction() {
+        let desired = Vector3::new(1.0, 0.1, 0.0).normalize();
+
+        assert_close(turn_towards(Vector3::unit_x(), desired, 0.5), desired);
+    }
+
+    #[test]
+    fn turn_towards_turns_by_at_most_the_angle() {
+        let turned = turn_towards(Vector3::unit_x(), Vector3::unit_y(), 0.5);
+
+        assert_close(turned, Vector3::new(0.5f32.cos(), 0.5f32.sin(), 0.0));
+    }
+
+    #[test]
+    fn turn_towards_turns_away_from_an_opposite_direction() {
+        let turned = turn_towards(Vector3::unit_x(), -Vector3::unit_x(), 0.5);
+
+        assert!((turned.magnitude() - 1.0).abs() < 1e-5);
+        assert!((turned.angle(Vector3::unit_x()).0 - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn projectiles_file_defines_the_mining_missle() {
+        let projectiles = load_projectiles(&mut MeshManager::new());
+        let missle = projectiles.get(MINING_MISSLE).unwrap();
+
+        assert_eq!(missle.hits, vec![Collider::ASTEROID]);
+        assert_eq!(missle.payload.damage, Asteroid::HEALTH);
+    }
+}
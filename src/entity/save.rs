@@ -0,0 +1,882 @@
+use super::{
+    blueprint::{Blueprint, BlueprintBlock, BlueprintError},
+    crew::{self, Crew},
+    gameplay::AsteroidField,
+    jobs::Construction,
+    movement::ShipBody,
+    objects::{self, Asteroid, Health, ObjectMeshes},
+    power::Battery,
+    projectile::{self, Guidance, GuidanceMode, Projectile, Projectiles},
+    ship::{self, BuildAction, Orientation, Payment},
+    structure::Debris,
+    BlockEntity, Collider, ColliderShape, Hitbox, Model, Parent, PlayerShip, RigidBody, Ship,
+    Transform,
+};
+use crate::block::{Blocks, Door, Laser, Miner, MinerSettings, TargetMode};
+use crate::item::{GameItem, Inventory};
+use cgmath::{Point2, Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// The version written into new save files. Only bump this when older saves need to be
+/// migrated, and teach `read_save` how to do it. New fields that have a `#[serde(default)]`
+/// load from older saves without one, which is why versions 2 to 13 are all read the same way.
+pub const SAVE_VERSION: u32 = 13;
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub ship: Blueprint,
+    /// Blocks on the ship that have taken damage. Version 2 saves have none.
+    #[serde(default)]
+    pub damaged_blocks: Vec<DamagedBlockSave>,
+    /// The charge of every battery on the ship. Older saves have none.
+    #[serde(default)]
+    pub batteries: Vec<BatterySave>,
+    /// Blocks that the crew haven't finished building. Older saves have none.
+    #[serde(default)]
+    pub constructions: Vec<ConstructionSave>,
+    /// The tiles of locked doors written by older saves, which didn't say whether each door
+    /// was a gadget
+    #[serde(default, skip_serializing)]
+    pub locked_doors: Vec<Point2<i16>>,
+    /// The doors that the player has locked. Older saves have none.
+    #[serde(default)]
+    pub doors: Vec<LockedDoorSave>,
+    /// The lasers that pick their own targets. Older saves have none.
+    #[serde(default)]
+    pub lasers: Vec<LaserSave>,
+    /// The miners that have been set up differently from a new one. Older saves have none.
+    #[serde(default)]
+    pub miners: Vec<MinerSave>,
+    /// Where the ship has flown to. Older saves start the ship at rest at the origin.
+    #[serde(default)]
+    pub ship_motion: Option<ShipMotionSave>,
+    /// The people living on the ship. Older saves start with a new crew.
+    #[serde(default)]
+    pub crew: Option<Vec<CrewSave>>,
+    /// The pieces that broke off of the ship. Older saves have none.
+    #[serde(default)]
+    pub debris: Vec<DebrisSave>,
+    pub inventory: BTreeMap<GameItem, u32>,
+    pub asteroids: Vec<AsteroidSave>,
+    /// Mining missles written by older saves, which are loaded as projectiles
+    #[serde(default, skip_serializing)]
+    pub missles: Vec<MissleSave>,
+    /// Projectiles in flight. Older saves have none.
+    #[serde(default)]
+    pub projectiles: Vec<ProjectileSave>,
+    pub asteroid_fields: Vec<AsteroidFieldSave>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DamagedBlockSave {
+    /// The tile that the block is rooted at
+    pub pos: Point2<i16>,
+    pub gadget: bool,
+    pub health: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BatterySave {
+    /// The tile that the battery is rooted at
+    pub pos: Point2<i16>,
+    pub gadget: bool,
+    pub charge: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ConstructionSave {
+    /// The tile that the block is rooted at
+    pub pos: Point2<i16>,
+    pub gadget: bool,
+    pub progress: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LockedDoorSave {
+    /// The tile that the door is rooted at
+    pub pos: Point2<i16>,
+    pub gadget: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LaserSave {
+    /// The tile that the laser is rooted at
+    pub pos: Point2<i16>,
+    pub gadget: bool,
+    pub mode: TargetMode,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MinerSave {
+    /// The tile that the miner is rooted at
+    pub pos: Point2<i16>,
+    pub gadget: bool,
+    pub settings: MinerSettings,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ShipMotionSave {
+    pub transform: Transform,
+    pub velocity: Vector3<f32>,
+    pub angular_velocity: f32,
+}
+
+/// Only the layout and motion of debris is saved. Its blocks come back at full health,
+/// and gadgets whose base block stayed behind on the ship are left out.
+#[derive(Serialize, Deserialize)]
+pub struct DebrisSave {
+    pub ship: Blueprint,
+    pub motion: ShipMotionSave,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CrewSave {
+    /// Where the crew member is standing, in ship tiles
+    pub position: Vector2<f32>,
+    pub breath: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AsteroidSave {
+    pub item: GameItem,
+    pub health: u32,
+    pub transform: Transform,
+    pub velocity: Vector3<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MissleSave {
+    /// Index into `SaveGame::asteroids`
+    pub target: usize,
+    pub transform: Transform,
+    pub velocity: Vector3<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProjectileSave {
+    pub projectile: Projectile,
+    pub guidance: Option<GuidanceSave>,
+    /// The radius of the collider written by older saves, where every projectile was a sphere
+    #[serde(default, skip_serializing)]
+    pub radius: f32,
+    /// The shape of the projectile's collider. Older saves only have the radius.
+    #[serde(default)]
+    pub shape: Option<ColliderShape>,
+    /// The collider groups that the projectile can hit
+    pub hits: Vec<usize>,
+    pub transform: Transform,
+    pub velocity: Vector3<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GuidanceSave {
+    pub mode: GuidanceMode,
+    pub turn_rate: f32,
+    /// Index into `SaveGame::asteroids`. Projectiles aimed at anything else are saved without a target.
+    pub target: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AsteroidFieldSave {
+    /// Indices into `SaveGame::asteroids`
+    pub asteroids: Vec<usize>,
+    pub tick: u16,
+    pub spawn_time: u16,
+    pub x_range: f32,
+}
+
+/// Version 1 saves, which were written before blocks could be rotated
+mod v1 {
+    use super::{AsteroidFieldSave, AsteroidSave, MissleSave};
+    use crate::entity::blueprint::BlueprintFloor;
+    use crate::item::GameItem;
+    use cgmath::Point2;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Deserialize)]
+    pub struct SaveGame {
+        pub ship: ShipSave,
+        pub inventory: BTreeMap<GameItem, u32>,
+        pub asteroids: Vec<AsteroidSave>,
+        pub missles: Vec<MissleSave>,
+        pub asteroid_fields: Vec<AsteroidFieldSave>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ShipSave {
+        pub blocks: Vec<BlockSave>,
+        pub gadgets: Vec<BlockSave>,
+        pub floors: Vec<BlueprintFloor>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct BlockSave {
+        pub block: String,
+        pub pos: Point2<i16>,
+    }
+}
+
+/// Only used to find out which version a save file was written with
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Format(ron::Error),
+    UnsupportedVersion(u32),
+    /// The saved ship could not be rebuilt
+    Blueprint(BlueprintError),
+    NoShip,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "Unable to access save file: {}", err),
+            SaveError::Format(err) => write!(f, "Invalid save file: {}", err),
+            SaveError::UnsupportedVersion(version) => write!(
+                f,
+                "Save version {} is not supported (current version is {})",
+                version, SAVE_VERSION
+            ),
+            SaveError::Blueprint(err) => write!(f, "{}", err),
+            SaveError::NoShip => write!(f, "There is no ship to save"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(err: std::io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+impl From<ron::Error> for SaveError {
+    fn from(err: ron::Error) -> Self {
+        SaveError::Format(err)
+    }
+}
+
+impl From<BlueprintError> for SaveError {
+    fn from(err: BlueprintError) -> Self {
+        SaveError::Blueprint(err)
+    }
+}
+
+/// Parses a save file, migrating it to the current version if it is older.
+pub fn read_save(text: &str) -> Result<SaveGame, SaveError> {
+    let header: SaveHeader = ron::de::from_str(text)?;
+
+    match header.version {
+        1 => Ok(migrate_v1(ron::de::from_str(text)?)),
+        2..=SAVE_VERSION => Ok(ron::de::from_str(text)?),
+        version => Err(SaveError::UnsupportedVersion(version)),
+    }
+}
+
+fn migrate_v1(save: v1::SaveGame) -> SaveGame {
+    // Every block used to face east, except for miners which were always turned around
+    let migrate_block = |block: v1::BlockSave| BlueprintBlock {
+        orientation: if block.block == "Miner" {
+            Orientation::West
+        } else {
+            Orientation::East
+        },
+        block: block.block,
+        pos: block.pos,
+    };
+
+    SaveGame {
+        version: SAVE_VERSION,
+        ship: Blueprint {
+            blocks: save.ship.blocks.into_iter().map(migrate_block).collect(),
+            gadgets: save.ship.gadgets.into_iter().map(migrate_block).collect(),
+            floors: save.ship.floors,
+        },
+        damaged_blocks: Vec::new(),
+        batteries: Vec::new(),
+        constructions: Vec::new(),
+        locked_doors: Vec::new(),
+        doors: Vec::new(),
+        lasers: Vec::new(),
+        miners: Vec::new(),
+        ship_motion: None,
+        crew: None,
+        debris: Vec::new(),
+        inventory: save.inventory,
+        asteroids: save.asteroids,
+        missles: save.missles,
+        projectiles: Vec::new(),
+        asteroid_fields: save.asteroid_fields,
+    }
+}
+
+pub fn write_save(world: &World) -> Result<String, SaveError> {
+    let blocks = world.fetch::<Blocks>();
+    let entities = world.entities();
+    let ships = world.read_component::<Ship>();
+    let block_entities = world.read_component::<BlockEntity>();
+    let batteries = world.read_component::<Battery>();
+    let constructions = world.read_component::<Construction>();
+    let doors = world.read_component::<Door>();
+    let lasers = world.read_component::<Laser>();
+    let miners = world.read_component::<Miner>();
+    let transforms = world.read_component::<Transform>();
+    let bodies = world.read_component::<RigidBody>();
+    let ship_bodies = world.read_component::<ShipBody>();
+    let crews = world.read_component::<Crew>();
+    let debris = world.read_component::<Debris>();
+    let parents = world.read_component::<Parent>();
+    let asteroid_storage = world.read_component::<Asteroid>();
+    let healths = world.read_component::<Health>();
+    let projectile_storage = world.read_component::<Projectile>();
+    let guidances = world.read_component::<Guidance>();
+    let colliders = world.read_component::<Collider>();
+    let fields = world.read_component::<AsteroidField>();
+
+    let ship_entity = world
+        .try_fetch::<PlayerShip>()
+        .map(|player_ship| player_ship.0)
+        .ok_or(SaveError::NoShip)?;
+    let ship = ships.get(ship_entity).ok_or(SaveError::NoShip)?;
+    let motion = |entity| {
+        transforms.get(entity).map(|transform| ShipMotionSave {
+            transform: transform.clone(),
+            velocity: bodies
+                .get(entity)
+                .map_or(Vector3::new(0.0, 0.0, 0.0), |body| body.velocity),
+            angular_velocity: ship_bodies
+                .get(entity)
+                .map_or(0.0, |body| body.angular_velocity),
+        })
+    };
+
+    // Every block and gadget on the ship, along with the tile it is rooted at
+    let mut ship_blocks = Vec::new();
+    for (pos, tile) in ship.tiles() {
+        for (entity, gadget) in [(tile.block(), false), (tile.gadget(), true)].iter() {
+            let root = entity.and_then(|entity| block_entities.get(entity));
+            if let (Some(entity), Some(block_entity)) = (entity, root) {
+                if block_entity.root() == *pos {
+                    ship_blocks.push((*pos, *gadget, *entity, *block_entity));
+                }
+            }
+        }
+    }
+    ship_blocks.sort_by_key(|(pos, gadget, _, _)| (pos.x, pos.y, *gadget));
+
+    let damaged_blocks = ship_blocks
+        .iter()
+        .filter_map(|(pos, gadget, entity, block_entity)| {
+            let health = healths.get(*entity)?.health();
+            let max_health = blocks.get_block(block_entity.block_id()).health;
+            Some(DamagedBlockSave {
+                pos: *pos,
+                gadget: *gadget,
+                health,
+            })
+            .filter(|_| health < max_health)
+        })
+        .collect();
+    let batteries = ship_blocks
+        .iter()
+        .filter_map(|(pos, gadget, entity, _)| {
+            batteries.get(*entity).map(|battery| BatterySave {
+                pos: *pos,
+                gadget: *gadget,
+                charge: battery.charge,
+            })
+        })
+        .collect();
+
+    let constructions = ship_blocks
+        .iter()
+        .filter_map(|(pos, gadget, entity, _)| {
+            constructions
+                .get(*entity)
+                .map(|construction| ConstructionSave {
+                    pos: *pos,
+                    gadget: *gadget,
+                    progress: construction.progress,
+                })
+        })
+        .collect();
+
+    let locked_doors = ship_blocks
+        .iter()
+        .filter(|(_, _, entity, _)| doors.get(*entity).is_some_and(|door| door.locked))
+        .map(|(pos, gadget, _, _)| LockedDoorSave {
+            pos: *pos,
+            gadget: *gadget,
+        })
+        .collect();
+
+    // Lasers are built in manual mode, so only the ones that have been switched over are saved
+    let lasers = ship_blocks
+        .iter()
+        .filter_map(|(pos, gadget, entity, _)| {
+            let mode = lasers.get(*entity)?.mode;
+            Some(LaserSave {
+                pos: *pos,
+                gadget: *gadget,
+                mode,
+            })
+            .filter(|_| mode != TargetMode::default())
+        })
+        .collect();
+    let miners = ship_blocks
+        .iter()
+        .filter_map(|(pos, gadget, entity, _)| {
+            let settings = miners.get(*entity)?.settings;
+            Some(MinerSave {
+                pos: *pos,
+                gadget: *gadget,
+                settings,
+            })
+            .filter(|_| settings != MinerSettings::default())
+        })
+        .collect();
+
+    let mut asteroid_indices = HashMap::new();
+    let mut asteroids = Vec::new();
+    for (entity, asteroid, health, transform, body) in
+        (&entities, &asteroid_storage, &healths, &transforms, &bodies).join()
+    {
+        asteroid_indices.insert(entity, asteroids.len());
+        asteroids.push(AsteroidSave {
+            item: asteroid.0,
+            health: health.health(),
+            transform: transform.clone(),
+            velocity: body.velocity,
+        });
+    }
+
+    let projectiles = (
+        &entities,
+        &projectile_storage,
+        &colliders,
+        &transforms,
+        &bodies,
+    )
+        .join()
+        .map(
+            |(entity, projectile, collider, transform, body)| ProjectileSave {
+                projectile: projectile.clone(),
+                guidance: guidances.get(entity).map(|guidance| GuidanceSave {
+                    mode: guidance.mode,
+                    turn_rate: guidance.turn_rate,
+                    target: guidance
+                        .target
+                        .and_then(|target| asteroid_indices.get(&target).copied()),
+                }),
+                radius: 0.0,
+                shape: Some(collider.hitbox.shape),
+                hits: collider.whitelist.clone(),
+                transform: transform.clone(),
+                velocity: body.velocity,
+            },
+        )
+        .collect();
+
+    let asteroid_fields = fields
+        .join()
+        .map(|field| AsteroidFieldSave {
+            asteroids: field
+                .asteroids
+                .iter()
+                .filter_map(|asteroid| asteroid_indices.get(asteroid).copied())
+                .collect(),
+            tick: field.tick,
+            spawn_time: field.spawn_time,
+            x_range: field.x_range,
+        })
+        .collect();
+
+    let save = SaveGame {
+        version: SAVE_VERSION,
+        ship: Blueprint::from_ship(world, ship),
+        damaged_blocks,
+        batteries,
+        constructions,
+        locked_doors: Vec::new(),
+        doors: locked_doors,
+        lasers,
+        miners,
+        ship_motion: motion(ship_entity),
+        crew: Some(
+            (&crews, &parents)
+                .join()
+                .filter(|(_, parent)| parent.entity == ship_entity)
+                .map(|(crew, _)| CrewSave {
+                    position: crew.position,
+                    breath: crew.breath,
+                })
+                .collect(),
+        ),
+        debris: (&entities, &ships, &debris)
+            .join()
+            .filter_map(|(entity, ship, _)| {
+                Some(DebrisSave {
+                    ship: Blueprint::from_ship(world, ship),
+                    motion: motion(entity)?,
+                })
+            })
+            .collect(),
+        inventory: world
+            .fetch::<Inventory>()
+            .items()
+            .iter()
+            .map(|(item, amount)| (*item, *amount))
+            .collect(),
+        asteroids,
+        missles: Vec::new(),
+        projectiles,
+        asteroid_fields,
+    };
+
+    Ok(ron::ser::to_string_pretty(
+        &save,
+        ron::ser::PrettyConfig::new(),
+    )?)
+}
+
+/// The build actions that rebuild every ship in a save
+pub struct SavedShips {
+    pub player: Vec<BuildAction>,
+    /// One list of actions for each piece of debris, in the order they were saved
+    pub debris: Vec<Vec<BuildAction>>,
+}
+
+/// Converts the saved ships into build actions, which validates every
+/// block and floor before the current world is torn down.
+pub fn ship_build_actions(world: &World, save: &SaveGame) -> Result<SavedShips, SaveError> {
+    let actions =
+        |blueprint: &Blueprint| blueprint.build_actions(world, &Ship::empty(), Vector2::new(0, 0));
+
+    Ok(SavedShips {
+        player: actions(&save.ship)?,
+        debris: save
+            .debris
+            .iter()
+            .map(|debris| actions(&debris.ship))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+/// Recreates the saved game inside of an empty world.
+pub fn restore_world(world: &mut World, save: SaveGame, actions: SavedShips) {
+    world.insert(Inventory::from_items(save.inventory.into_iter().collect()));
+
+    let ship = ship::create_empty_ship(world);
+    // The ship is moved before it is built, so that its parts are placed around it
+    if let Some(motion) = save.ship_motion {
+        world
+            .write_component::<Transform>()
+            .insert(ship, motion.transform)
+            .expect("Unable to restore ship position");
+        world
+            .write_component::<RigidBody>()
+            .insert(
+                ship,
+                RigidBody {
+                    velocity: motion.velocity,
+                },
+            )
+            .expect("Unable to restore ship velocity");
+        world
+            .write_component::<ShipBody>()
+            .insert(
+                ship,
+                ShipBody {
+                    angular_velocity: motion.angular_velocity,
+                },
+            )
+            .expect("Unable to restore ship spin");
+    }
+    ship::execute_build_actions(world, ship, &actions.player, Payment::Free);
+    world.insert(PlayerShip(ship));
+    for (debris, debris_actions) in save.debris.into_iter().zip(actions.debris) {
+        let entity = world
+            .create_entity()
+            .with(Ship::empty())
+            .with(Debris)
+            .with(debris.motion.transform)
+            .with(RigidBody {
+                velocity: debris.motion.velocity,
+            })
+            .with(ShipBody {
+                angular_velocity: debris.motion.angular_velocity,
+            })
+            .build();
+        ship::execute_build_actions(world, entity, &debris_actions, Payment::Free);
+    }
+    // Crew members are placed on floors, which need to exist first
+    world.maintain();
+    match save.crew {
+        Some(members) => {
+            for member in members {
+                crew::add_crew(world, ship, Crew::new(member.position, member.breath));
+            }
+        }
+        None => crew::spawn_crew(world, ship, Crew::STARTING_CREW),
+    }
+
+    {
+        let entities = world.entities();
+        let lazy_update = world.fetch::<LazyUpdate>();
+        let meshes = world.fetch::<ObjectMeshes>();
+        let definitions = world.fetch::<Projectiles>();
+
+        let asteroids: Vec<Entity> = save
+            .asteroids
+            .into_iter()
+            .map(|asteroid| {
+                objects::build_asteroid(
+                    &meshes,
+                    lazy_update.create_entity(&entities),
+                    asteroid.item,
+                    asteroid.health,
+                    asteroid.transform,
+                    asteroid.velocity,
+                )
+            })
+            .collect();
+
+        let mining_missle = definitions.get(projectile::MINING_MISSLE);
+        for missle in save.missles {
+            if let (Some(target), Some(definition)) = (asteroids.get(missle.target), mining_missle)
+            {
+                let position = missle.transform.position;
+                let entity = projectile::launch_projectile(
+                    definition,
+                    lazy_update.create_entity(&entities),
+                    Some(*target),
+                    position,
+                    Vector3::unit_z(),
+                );
+                lazy_update.insert(entity, missle.transform);
+                lazy_update.insert(
+                    entity,
+                    RigidBody {
+                        velocity: missle.velocity,
+                    },
+                );
+            }
+        }
+
+        // Projectiles whose definition has since been removed are left out
+        for saved in save.projectiles {
+            let definition = match definitions.get(&saved.projectile.kind) {
+                Some(definition) => definition,
+                None => continue,
+            };
+            let mut builder = lazy_update
+                .create_entity(&entities)
+                .with(saved.transform)
+                .with(Model::new(definition.mesh_id))
+                .with(RigidBody {
+                    velocity: saved.velocity,
+                })
+                .with(Collider::new(
+                    Hitbox::with_shape(saved.shape.unwrap_or(ColliderShape::Sphere(saved.radius))),
+                    Collider::MISSLE,
+                    saved.hits,
+                ))
+                .with(saved.projectile);
+            if let Some(guidance) = saved.guidance {
+                builder = builder.with(Guidance {
+                    mode: guidance.mode,
+                    turn_rate: guidance.turn_rate,
+                    target: guidance
+                        .target
+                        .and_then(|index| asteroids.get(index).copied()),
+                    gravity: definition.gravity,
+                });
+            }
+            builder.build();
+        }
+
+        for field in save.asteroid_fields {
+            lazy_update
+                .create_entity(&entities)
+                .with(AsteroidField {
+                    asteroids: field
+                        .asteroids
+                        .iter()
+                        .filter_map(|index| asteroids.get(*index).copied())
+                        .collect(),
+                    tick: field.tick,
+                    spawn_time: field.spawn_time,
+                    x_range: field.x_range,
+                })
+                .build();
+        }
+    }
+
+    world.maintain();
+
+    // Blocks are built at full health and without any charge, so their state is restored once they exist
+    let ships = world.read_component::<Ship>();
+    let mut healths = world.write_component::<Health>();
+    let mut batteries = world.write_component::<Battery>();
+    let mut constructions = world.write_component::<Construction>();
+    let mut doors = world.write_component::<Door>();
+    let mut lasers = world.write_component::<Laser>();
+    let mut miners = world.write_component::<Miner>();
+    let find_block = |pos: Point2<i16>, gadget: bool| {
+        let tile = ships.get(ship)?.tile(pos)?;
+        if gadget {
+            tile.gadget()
+        } else {
+            tile.block()
+        }
+    };
+    for damaged in save.damaged_blocks {
+        if let Some(entity) = find_block(damaged.pos, damaged.gadget) {
+            healths
+                .insert(entity, Health(damaged.health))
+                .expect("Unable to restore block health");
+        }
+    }
+    for battery in save.batteries {
+        if let Some(entity) = find_block(battery.pos, battery.gadget) {
+            batteries
+                .insert(
+                    entity,
+                    Battery {
+                        charge: battery.charge,
+                    },
+                )
+                .expect("Unable to restore battery charge");
+        }
+    }
+    for construction in save.constructions {
+        if let Some(entity) = find_block(construction.pos, construction.gadget) {
+            constructions
+                .insert(
+                    entity,
+                    Construction {
+                        progress: construction.progress,
+                    },
+                )
+                .expect("Unable to restore construction progress");
+        }
+    }
+    for saved in save.doors {
+        let door = find_block(saved.pos, saved.gadget).and_then(|entity| doors.get_mut(entity));
+        if let Some(door) = door {
+            door.locked = true;
+        }
+    }
+    // Older saves only kept the tile, so the door could be either the block or the gadget there
+    for pos in save.locked_doors {
+        let door = [false, true]
+            .iter()
+            .filter_map(|gadget| find_block(pos, *gadget))
+            .find(|entity| doors.contains(*entity));
+        if let Some(door) = door.and_then(|entity| doors.get_mut(entity)) {
+            door.locked = true;
+        }
+    }
+    for saved in save.lasers {
+        let laser = find_block(saved.pos, saved.gadget).and_then(|entity| lasers.get_mut(entity));
+        if let Some(laser) = laser {
+            laser.mode = saved.mode;
+        }
+    }
+    for saved in save.miners {
+        let miner = find_block(saved.pos, saved.gadget).and_then(|entity| miners.get_mut(entity));
+        if let Some(miner) = miner {
+            miner.settings = saved.settings;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1_SAVE: &str = r#"(
+        version: 1,
+        ship: (
+            blocks: [
+                (block: "wall", pos: (x: 0, y: 0)),
+                (block: "Miner", pos: (x: -1, y: 3)),
+            ],
+            gadgets: [
+                (block: "Laser", pos: (x: 0, y: 0)),
+            ],
+            floors: [
+                (floor: "metal", pos: (x: 1, y: 0)),
+            ],
+        ),
+        inventory: {Iron: 7, Copper: 2},
+        asteroids: [],
+        missles: [],
+        asteroid_fields: [],
+    )"#;
+
+    #[test]
+    fn read_save_migrates_version_1() {
+        let save = read_save(V1_SAVE).unwrap();
+
+        assert_eq!(save.version, SAVE_VERSION);
+        let blocks: Vec<_> = save
+            .ship
+            .blocks
+            .iter()
+            .map(|block| (block.block.as_str(), block.pos, block.orientation))
+            .collect();
+        assert_eq!(
+            blocks,
+            vec![
+                ("wall", Point2::new(0, 0), Orientation::East),
+                ("Miner", Point2::new(-1, 3), Orientation::West),
+            ]
+        );
+        assert_eq!(save.ship.gadgets.len(), 1);
+        assert_eq!(save.ship.gadgets[0].orientation, Orientation::East);
+        assert_eq!(save.ship.floors.len(), 1);
+        assert_eq!(save.ship.floors[0].pos, Point2::new(1, 0));
+        assert_eq!(save.inventory.get(&GameItem::Iron), Some(&7));
+        assert_eq!(save.inventory.get(&GameItem::Copper), Some(&2));
+        assert!(save.constructions.is_empty());
+        assert!(save.ship_motion.is_none());
+        assert!(save.debris.is_empty());
+    }
+
+    #[test]
+    fn read_save_reads_every_version_the_same_way() {
+        for version in 2..=SAVE_VERSION {
+            let text = V1_SAVE.replace("version: 1", &format!("version: {}", version));
+            let text = text.replace("(block: \"wall\"", "(orientation: North, block: \"wall\"");
+            let text = text.replace("(block: \"Miner\"", "(orientation: West, block: \"Miner\"");
+            let text = text.replace("(block: \"Laser\"", "(orientation: East, block: \"Laser\"");
+
+            let save = read_save(&text).unwrap();
+
+            assert_eq!(save.ship.blocks[0].orientation, Orientation::North);
+            assert_eq!(save.inventory.get(&GameItem::Iron), Some(&7));
+        }
+    }
+
+    #[test]
+    fn read_save_rejects_newer_versions() {
+        let text = format!("(version: {})", SAVE_VERSION + 1);
+
+        assert!(matches!(
+            read_save(&text),
+            Err(SaveError::UnsupportedVersion(version)) if version == SAVE_VERSION + 1
+        ));
+    }
+}
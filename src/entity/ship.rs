@@ -1,208 +1,1030 @@
-use super::{Collider, Model, Transform};
+use super::{
+    atmosphere::Atmosphere,
+    blueprint::{Blueprint, DEFAULT_BLUEPRINT_PATH},
+    jobs::{JobQueue, OperatorStation},
+    movement::ShipBody,
+    objects::Health,
+    power::{Battery, PowerConsumer},
+    Collider, Model, Parent, RigidBody, ToBeRemoved, Transform,
+};
 use crate::block::{BlockId, Blocks};
-use crate::floor::{Floor, Floors};
-use cgmath::Point2;
+use crate::floor::Floor;
+use crate::item::{Inventory, NotEnoughItems};
+use cgmath::{Point2, Vector2, Vector3};
+use serde::{Deserialize, Serialize};
 use specs::{prelude::*, world::EntitiesRes, Component};
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 
 #[derive(Component)]
 #[storage(VecStorage)]
 pub struct Ship {
+    /// Only tiles that hold something are stored, so the ship grows as it is built outward
     tiles: HashMap<Point2<i16>, Tile>,
+    /// The area that the ship is allowed to grow into, if it is limited at all
+    limits: Option<TileBounds>,
+    /// Set when blocks or floors are removed, so that the ship's connectivity is checked again
+    structure_changed: bool,
+    /// Changes whenever anything is built or removed, so that anything worked
+    /// out from the ship's layout (such as rooms and paths) can be found again
+    layout_version: u32,
 }
 
-#[derive(Clone, Debug)]
+impl Ship {
+    /// Creates a ship without any blocks or floors, which can grow in every direction
+    pub fn empty() -> Ship {
+        Ship {
+            tiles: HashMap::new(),
+            limits: None,
+            structure_changed: false,
+            layout_version: 0,
+        }
+    }
+
+    /// Changes the area that the ship can be built in. Anything already
+    /// built outside of the new limits is left where it is.
+    pub fn set_limits(&mut self, limits: Option<TileBounds>) {
+        self.limits = limits;
+    }
+
+    /// Whether the ship's limits allow it to be built on the tile
+    pub fn in_bounds(&self, pos: Point2<i16>) -> bool {
+        self.limits.is_none_or(|limits| limits.contains(pos))
+    }
+
+    /// Every tile that holds a block, gadget, or floor
+    pub fn tiles(&self) -> impl Iterator<Item = (&Point2<i16>, &Tile)> {
+        self.tiles.iter()
+    }
+
+    /// The tile at the given position. Returns None if nothing has been built there.
+    pub fn tile(&self, pos: Point2<i16>) -> Option<&Tile> {
+        self.tiles.get(&pos)
+    }
+
+    /// The tiles directly next to the given position that have something built on them
+    pub fn neighbours(&self, pos: Point2<i16>) -> impl Iterator<Item = (Point2<i16>, &Tile)> {
+        [(1, 0), (-1, 0), (0, 1), (0, -1)]
+            .iter()
+            .filter_map(move |(x, y)| {
+                let next = Point2::new(pos.x.checked_add(*x)?, pos.y.checked_add(*y)?);
+                self.tiles.get(&next).map(|tile| (next, tile))
+            })
+    }
+
+    /// The smallest area that covers every tile with something built on it,
+    /// or None if the ship is empty
+    pub fn bounding_box(&self) -> Option<TileBounds> {
+        let mut positions = self.tiles.keys();
+        let first = *positions.next()?;
+
+        Some(
+            positions.fold(TileBounds::new(first, first), |bounds, pos| {
+                TileBounds::new(
+                    Point2::new(bounds.min.x.min(pos.x), bounds.min.y.min(pos.y)),
+                    Point2::new(bounds.max.x.max(pos.x), bounds.max.y.max(pos.y)),
+                )
+            }),
+        )
+    }
+
+    /// Every block on the ship, each listed once no matter how many tiles it covers
+    pub fn blocks(&self) -> Vec<Entity> {
+        self.collect_entities(|tile| vec![tile.block])
+    }
+
+    /// Every gadget on the ship, each listed once no matter how many tiles it covers
+    pub fn gadgets(&self) -> Vec<Entity> {
+        self.collect_entities(|tile| vec![tile.gadget])
+    }
+
+    /// Checks if the block can be built with its bottom left corner at pos.
+    /// Returns the height that the bottom of the block will be placed at.
+    /// `block_id_of` looks up which block an entity on the ship is.
+    pub fn check_block(
+        &self,
+        blocks: &Blocks,
+        block_id_of: impl Fn(Entity) -> Option<BlockId>,
+        pos: Point2<i16>,
+        block_id: BlockId,
+        orientation: Orientation,
+    ) -> Result<f32, BuildError> {
+        let block = blocks.get_block(block_id);
+        let mut base_height: f32 = 0.0;
+
+        let tiles = footprint(pos, orientation.rotate_size(block.size))
+            .ok_or(BuildError::OutOfBounds(pos))?;
+        for pos in tiles {
+            if !self.in_bounds(pos) {
+                return Err(BuildError::OutOfBounds(pos));
+            }
+            let tile = self.tiles.get(&pos);
+
+            if !block.is_gadget {
+                if tile.and_then(Tile::block).is_some() {
+                    return Err(BuildError::TileOccupied(pos));
+                }
+                continue;
+            }
+
+            if tile.and_then(Tile::gadget).is_some() {
+                return Err(BuildError::TileOccupied(pos));
+            }
+
+            // Gadgets sit on top of the tallest block below them
+            let base = tile
+                .and_then(Tile::block)
+                .and_then(&block_id_of)
+                .ok_or(BuildError::MissingBase(pos))?;
+            base_height = base_height.max(blocks.get_block(base).height);
+        }
+
+        Ok(base_height)
+    }
+
+    pub fn check_floor(&self, pos: Point2<i16>) -> Result<(), BuildError> {
+        if !self.in_bounds(pos) {
+            return Err(BuildError::OutOfBounds(pos));
+        }
+
+        if self.tiles.get(&pos).and_then(Tile::floor).is_some() {
+            Err(BuildError::TileOccupied(pos))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns true if blocks or floors were removed since the last call
+    pub fn take_structure_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.structure_changed, false)
+    }
+
+    pub fn layout_version(&self) -> u32 {
+        self.layout_version
+    }
+
+    /// Marks the layout as changed, for changes that don't go through the ship's tiles
+    /// (such as a block being finished)
+    pub fn layout_changed(&mut self) {
+        self.layout_version = self.layout_version.wrapping_add(1);
+    }
+
+    /// Whether crew can stand on the tile, which needs a floor without a block on it.
+    /// `is_passable` picks out the blocks that crew can walk through anyway, such as open doors.
+    pub fn is_walkable(&self, pos: Point2<i16>, is_passable: impl Fn(Entity) -> bool) -> bool {
+        self.tiles
+            .get(&pos)
+            .is_some_and(|tile| tile.floor.is_some() && tile.block.is_none_or(is_passable))
+    }
+
+    /// Finds the shortest path over walkable tiles using A*. The path ends at the
+    /// goal and doesn't include the start, so it is empty when the start is the goal.
+    /// Returns None if the goal can't be reached.
+    pub fn find_path(
+        &self,
+        start: Point2<i16>,
+        goal: Point2<i16>,
+        is_passable: impl Fn(Entity) -> bool + Copy,
+    ) -> Option<Vec<Point2<i16>>> {
+        if !self.is_walkable(goal, is_passable) {
+            return None;
+        }
+        if start == goal {
+            return Some(Vec::new());
+        }
+
+        let estimate = |pos: Point2<i16>| {
+            (i32::from(pos.x) - i32::from(goal.x)).unsigned_abs()
+                + (i32::from(pos.y) - i32::from(goal.y)).unsigned_abs()
+        };
+        // Points aren't ordered, so tiles are queued as (x, y) tuples
+        let mut open = BinaryHeap::new();
+        open.push(Reverse((estimate(start), 0, (start.x, start.y))));
+        let mut costs = HashMap::new();
+        costs.insert(start, 0);
+        let mut came_from = HashMap::new();
+
+        while let Some(Reverse((_, cost, (x, y)))) = open.pop() {
+            let pos = Point2::new(x, y);
+            if pos == goal {
+                let mut path = vec![goal];
+                while let Some(previous) = came_from.get(path.last().unwrap()) {
+                    if *previous == start {
+                        break;
+                    }
+                    path.push(*previous);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            // A cheaper way to this tile was found after it was queued
+            if cost > costs[&pos] {
+                continue;
+            }
+
+            for (next, _) in self.neighbours(pos) {
+                let next_cost = cost + 1;
+                if self.is_walkable(next, is_passable)
+                    && costs.get(&next).is_none_or(|old| next_cost < *old)
+                {
+                    costs.insert(next, next_cost);
+                    came_from.insert(next, pos);
+                    open.push(Reverse((
+                        next_cost + estimate(next),
+                        next_cost,
+                        (next.x, next.y),
+                    )));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Finds every room on the ship. Rooms are areas of floor tiles that are
+    /// connected to each other without passing through a block that `is_airtight` accepts.
+    pub fn rooms(&self, is_airtight: impl Fn(Entity) -> bool) -> Vec<Vec<Point2<i16>>> {
+        let is_airtight = &is_airtight;
+        let is_room = |tile: &Tile| tile.floor.is_some() && !tile.block.is_some_and(is_airtight);
+        self.connected_groups(&mut HashSet::new(), is_room)
+    }
+
+    /// The tiles holding a block that `is_core` accepts
+    pub fn core_tiles(&self, is_core: impl Fn(Entity) -> bool) -> Vec<Point2<i16>> {
+        let mut cores: Vec<Point2<i16>> = self
+            .tiles
+            .iter()
+            .filter(|(_, tile)| tile.block.is_some_and(&is_core))
+            .map(|(pos, _)| *pos)
+            .collect();
+        cores.sort_by_key(|pos| (pos.x, pos.y));
+        cores
+    }
+
+    /// Finds the groups of connected tiles that are cut off from every core tile.
+    /// Tiles are connected when they are next to each other and both hold a block or floor.
+    pub fn find_detached(&self, cores: &[Point2<i16>]) -> Vec<Vec<Point2<i16>>> {
+        let mut visited = HashSet::new();
+        self.flood_fill(cores.to_vec(), &mut visited, Tile::is_solid);
+        self.connected_groups(&mut visited, Tile::is_solid)
+    }
+
+    /// Splits the ship's blocks into power grids. Blocks next to each other share
+    /// a grid, and gadgets are connected to the blocks below them.
+    pub fn power_grids(&self) -> Vec<Vec<Entity>> {
+        let has_block = |tile: &Tile| tile.block.is_some();
+
+        self.connected_groups(&mut HashSet::new(), has_block)
+            .into_iter()
+            .map(|group| {
+                let mut entities: Vec<Entity> = group
+                    .iter()
+                    .flat_map(|pos| vec![self.tiles[pos].block, self.tiles[pos].gadget])
+                    .flatten()
+                    .collect();
+                entities.sort();
+                entities.dedup();
+                entities
+            })
+            .collect()
+    }
+
+    /// Splits the tiles accepted by `connects` that haven't been visited yet into connected groups
+    fn connected_groups(
+        &self,
+        visited: &mut HashSet<Point2<i16>>,
+        connects: impl Fn(&Tile) -> bool + Copy,
+    ) -> Vec<Vec<Point2<i16>>> {
+        // Tiles are stored in a HashMap, so sort them to keep the groups stable
+        let mut tiles: Vec<Point2<i16>> = self
+            .tiles
+            .iter()
+            .filter(|(_, tile)| connects(tile))
+            .map(|(pos, _)| *pos)
+            .collect();
+        tiles.sort_by_key(|pos| (pos.x, pos.y));
+
+        let mut groups = Vec::new();
+        for pos in tiles {
+            if !visited.contains(&pos) {
+                groups.push(self.flood_fill(vec![pos], visited, connects));
+            }
+        }
+
+        groups
+    }
+
+    /// Collects every tile accepted by `connects` that is connected to the
+    /// starting tiles and hasn't been visited yet
+    fn flood_fill(
+        &self,
+        start: Vec<Point2<i16>>,
+        visited: &mut HashSet<Point2<i16>>,
+        connects: impl Fn(&Tile) -> bool,
+    ) -> Vec<Point2<i16>> {
+        let mut group = Vec::new();
+        let mut stack: Vec<Point2<i16>> = start
+            .into_iter()
+            .filter(|pos| visited.insert(*pos))
+            .collect();
+
+        while let Some(pos) = stack.pop() {
+            group.push(pos);
+            for (next, tile) in self.neighbours(pos) {
+                if connects(tile) && visited.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+
+        group
+    }
+
+    /// Moves everything on the given tiles into a new ship. Gadgets that also
+    /// cover tiles outside of the group go with it, so nothing is split in half.
+    pub fn detach(&mut self, tiles: &[Point2<i16>]) -> Ship {
+        let mut detached = HashMap::new();
+        for pos in tiles {
+            if let Some(tile) = self.tiles.remove(pos) {
+                detached.insert(*pos, tile);
+            }
+        }
+
+        for gadget in detached.values().filter_map(|tile| tile.gadget) {
+            self.clear_entity(gadget);
+        }
+        self.layout_changed();
+
+        Ship {
+            tiles: detached,
+            ..Ship::empty()
+        }
+    }
+
+    /// Removes a block or gadget from the ship. Gadgets can't exist without the
+    /// block below them, so removing a block removes the gadgets on top of it too.
+    /// Returns every entity that was removed, starting with the given one.
+    pub fn remove_block(&mut self, entity: Entity) -> Vec<Entity> {
+        if self.tiles.values().any(|tile| tile.block == Some(entity)) {
+            self.structure_changed = true;
+        }
+
+        let covered = self.clear_entity(entity);
+        let mut gadgets: Vec<Entity> = covered
+            .iter()
+            .filter_map(|pos| self.tiles.get(pos).and_then(Tile::gadget))
+            .collect();
+        gadgets.sort();
+        gadgets.dedup();
+
+        for gadget in &gadgets {
+            self.clear_entity(*gadget);
+        }
+
+        Some(entity).into_iter().chain(gadgets).collect()
+    }
+
+    /// Every block, gadget, and floor entity on the ship
+    pub fn entities(&self) -> Vec<Entity> {
+        self.collect_entities(|tile| vec![tile.block, tile.gadget, tile.floor])
+    }
+
+    /// The entities picked out of each tile, without any repeats
+    fn collect_entities(&self, pick: impl Fn(&Tile) -> Vec<Option<Entity>>) -> Vec<Entity> {
+        let mut entities: Vec<Entity> = self.tiles.values().flat_map(pick).flatten().collect();
+        entities.sort();
+        entities.dedup();
+        entities
+    }
+
+    /// The tile at the given position, which is added to the ship if it was empty
+    fn tile_mut(&mut self, pos: Point2<i16>) -> &mut Tile {
+        self.layout_changed();
+        self.tiles.entry(pos).or_default()
+    }
+
+    /// Removes the entity from every tile that it covers,
+    /// and returns the positions of those tiles.
+    fn clear_entity(&mut self, entity: Entity) -> Vec<Point2<i16>> {
+        let mut covered = Vec::new();
+
+        for (pos, tile) in self.tiles.iter_mut() {
+            for slot in [&mut tile.block, &mut tile.gadget, &mut tile.floor].iter_mut() {
+                if **slot == Some(entity) {
+                    **slot = None;
+                    covered.push(*pos);
+                }
+            }
+        }
+        self.tiles.retain(|_, tile| !tile.is_empty());
+        self.layout_changed();
+
+        covered
+    }
+}
+
+/// The ship controlled by the player
+pub struct PlayerShip(pub Entity);
+
+/// A rectangle of tiles, where both corners are inside of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileBounds {
+    pub min: Point2<i16>,
+    pub max: Point2<i16>,
+}
+
+impl TileBounds {
+    pub fn new(min: Point2<i16>, max: Point2<i16>) -> Self {
+        TileBounds { min, max }
+    }
+
+    pub fn contains(&self, pos: Point2<i16>) -> bool {
+        (self.min.x..=self.max.x).contains(&pos.x) && (self.min.y..=self.max.y).contains(&pos.y)
+    }
+
+    /// The number of tiles across (x) and up (y) the rectangle
+    pub fn size(&self) -> Point2<u16> {
+        Point2::new(
+            (self.max.x - self.min.x) as u16 + 1,
+            (self.max.y - self.min.y) as u16 + 1,
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct Tile {
     block: Option<Entity>,
     gadget: Option<Entity>,
     floor: Option<Entity>,
 }
 
-#[derive(Component)]
+impl Tile {
+    /// Whether the tile is part of the ship's structure
+    fn is_solid(&self) -> bool {
+        self.block.is_some() || self.floor.is_some()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.block.is_none() && self.gadget.is_none() && self.floor.is_none()
+    }
+
+    pub fn block(&self) -> Option<Entity> {
+        self.block
+    }
+
+    pub fn gadget(&self) -> Option<Entity> {
+        self.gadget
+    }
+
+    pub fn floor(&self) -> Option<Entity> {
+        self.floor
+    }
+}
+
+/// Attached to every block and gadget that is part of a ship
+#[derive(Component, Clone, Copy)]
 #[storage(VecStorage)]
 pub struct BlockEntity {
     block_id: BlockId,
     root: Point2<i16>,
+    orientation: Orientation,
+}
+
+impl BlockEntity {
+    pub fn block_id(&self) -> BlockId {
+        self.block_id
+    }
+
+    pub fn root(&self) -> Point2<i16> {
+        self.root
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Where the block sits on its ship, with its bottom at the given height
+    pub fn ship_transform(&self, blocks: &Blocks, height: f32) -> Transform {
+        let size = self
+            .orientation
+            .rotate_size(blocks.get_block(self.block_id).size);
+        let center = footprint_center(self.root, size);
+        let mut transform = Transform::from_position(center.x, center.y, height);
+        transform.set_rotation_z(self.orientation.angle());
+        transform
+    }
+}
+
+/// The direction that a block faces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Orientation {
+    #[default]
+    East,
+    North,
+    West,
+    South,
+}
+
+impl Orientation {
+    /// The rotation around the z axis, counter-clockwise from east
+    pub fn angle(self) -> f32 {
+        match self {
+            Orientation::East => 0.0,
+            Orientation::North => crate::PI / 2.0,
+            Orientation::West => crate::PI,
+            Orientation::South => crate::PI * 1.5,
+        }
+    }
+
+    /// A unit vector pointing in the direction that the block faces
+    pub fn direction(self) -> Vector3<f32> {
+        match self {
+            Orientation::East => Vector3::new(1.0, 0.0, 0.0),
+            Orientation::North => Vector3::new(0.0, 1.0, 0.0),
+            Orientation::West => Vector3::new(-1.0, 0.0, 0.0),
+            Orientation::South => Vector3::new(0.0, -1.0, 0.0),
+        }
+    }
+
+    /// The next orientation when turning counter-clockwise
+    pub fn rotated(self) -> Self {
+        match self {
+            Orientation::East => Orientation::North,
+            Orientation::North => Orientation::West,
+            Orientation::West => Orientation::South,
+            Orientation::South => Orientation::East,
+        }
+    }
+
+    /// The grid size of a block once it has been turned to face this way
+    pub fn rotate_size(self, size: Point2<u16>) -> Point2<u16> {
+        match self {
+            Orientation::East | Orientation::West => size,
+            Orientation::North | Orientation::South => Point2::new(size.y, size.x),
+        }
+    }
 }
 
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct FloorEntity(pub Floor);
+
+#[derive(Clone)]
 pub enum BuildAction {
-    BuildBlock(Point2<i16>, BlockId),
+    /// Builds a block (or gadget) with its bottom left corner at the given tile
+    BuildBlock(Point2<i16>, BlockId, Orientation),
+    /// Removes the block covering the given tile, along with any gadgets on top of it
     RemoveBlock(Point2<i16>),
+    /// Removes the gadget covering the given tile, leaving the block below it
+    RemoveGadget(Point2<i16>),
     BuildFloor(Point2<i16>, Floor),
     RemoveFloor(Point2<i16>),
 }
 
-pub fn execute_build_actions(world: &mut World, ship: Entity, actions: &[BuildAction]) {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// The tile is already taken by another block, gadget, or floor
+    TileOccupied(Point2<i16>),
+    /// Gadgets can only be placed on top of blocks
+    MissingBase(Point2<i16>),
+    /// The tile is outside of the ship's limits
+    OutOfBounds(Point2<i16>),
+    /// The inventory doesn't hold enough items to pay for the block
+    NotEnoughItems(NotEnoughItems),
+}
+
+/// Whether build actions are paid for with items from the inventory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Payment {
+    /// Building takes the block's cost out of the inventory, and removing refunds it
+    Inventory,
+    /// Used to recreate existing ships, such as the initial ship or a saved one
+    Free,
+}
+
+/// The tiles covered by a block of the given size placed at root.
+/// Returns None if the block would reach past the largest tile position.
+pub fn footprint(root: Point2<i16>, size: Point2<u16>) -> Option<Vec<Point2<i16>>> {
+    let mut tiles = Vec::with_capacity(size.x as usize * size.y as usize);
+
+    for x in 0..size.x as i16 {
+        for y in 0..size.y as i16 {
+            tiles.push(Point2::new(root.x.checked_add(x)?, root.y.checked_add(y)?));
+        }
+    }
+
+    Some(tiles)
+}
+
+/// The position of the center of a block of the given size placed at root
+pub fn footprint_center(root: Point2<i16>, size: Point2<u16>) -> Point2<f32> {
+    Point2::new(
+        root.x as f32 + (size.x as f32 - 1.0) / 2.0,
+        root.y as f32 + (size.y as f32 - 1.0) / 2.0,
+    )
+}
+
+/// The result of executing a batch of build actions
+pub struct BuildOutcome {
+    /// Why each of the skipped actions couldn't be executed
+    pub errors: Vec<BuildError>,
+    /// The actions that will undo everything that was executed
+    pub inverse: Vec<BuildAction>,
+    /// Every block and gadget that was built. These are created lazily, so they
+    /// won't have any components until the world is maintained.
+    pub built: Vec<(Entity, BlockEntity)>,
+}
+
+/// Executes the build actions in order. Actions that are not valid for the
+/// current state of the ship (or that can't be paid for) are skipped.
+pub fn execute_build_actions(
+    world: &mut World,
+    ship_entity: Entity,
+    actions: &[BuildAction],
+    payment: Payment,
+) -> BuildOutcome {
     let lazy_update = world.fetch::<LazyUpdate>();
     let entities = world.fetch::<EntitiesRes>();
     let mut ships = world.write_component::<Ship>();
-    let ship = ships.get_mut(ship).unwrap();
+    let ship = ships.get_mut(ship_entity).unwrap();
+    let ship_transform = world
+        .read_component::<Transform>()
+        .get(ship_entity)
+        .cloned()
+        .unwrap_or_else(|| Transform::from_position(0.0, 0.0, 0.0));
     let blocks = world.fetch::<Blocks>();
     let block_entities = world.read_component::<BlockEntity>();
+    let floor_entities = world.read_component::<FloorEntity>();
+    let mut to_be_removed = world.fetch_mut::<ToBeRemoved>();
+    let mut inventory = world.fetch_mut::<Inventory>();
+    let mut errors = Vec::new();
+    // The inverse of each executed action, which are undone in reverse order
+    let mut inverses: Vec<Vec<BuildAction>> = Vec::new();
+
+    // Entities are created lazily, so the components of anything built
+    // during this batch won't be in the storages yet
+    let mut built_blocks: HashMap<Entity, BlockEntity> = HashMap::new();
+    let mut built_floors: HashMap<Entity, Floor> = HashMap::new();
+    let mut built = Vec::new();
+    let block_entity_of = |built_blocks: &HashMap<Entity, BlockEntity>, entity: Entity| {
+        block_entities
+            .get(entity)
+            .or_else(|| built_blocks.get(&entity))
+            .copied()
+    };
+
+    // Refunds what a block or gadget entity cost to build, and returns the action to rebuild it
+    let remove_block_entity = |inventory: &mut Inventory, block_entity: BlockEntity| {
+        if payment == Payment::Inventory {
+            for (item, amount) in &blocks.get_block(block_entity.block_id).cost {
+                inventory.change_amount(*item, *amount);
+            }
+        }
+
+        BuildAction::BuildBlock(
+            block_entity.root,
+            block_entity.block_id,
+            block_entity.orientation,
+        )
+    };
 
     for action in actions {
         match action {
-            BuildAction::BuildBlock(pos, block_id) if blocks.get_block(*block_id).is_gadget => {
+            BuildAction::BuildBlock(pos, block_id, orientation) => {
                 let block = blocks.get_block(*block_id);
+                let tiles = match footprint(*pos, orientation.rotate_size(block.size)) {
+                    Some(tiles) => tiles,
+                    None => {
+                        errors.push(BuildError::OutOfBounds(*pos));
+                        continue;
+                    }
+                };
+                let block_id_of =
+                    |entity| block_entity_of(&built_blocks, entity).map(|block| block.block_id);
+                let result = ship
+                    .check_block(&blocks, block_id_of, *pos, *block_id, *orientation)
+                    .and_then(|height| match payment {
+                        Payment::Inventory => inventory
+                            .remove_items(&block.cost)
+                            .map(|_| height)
+                            .map_err(BuildError::NotEnoughItems),
+                        Payment::Free => Ok(height),
+                    });
+                let height = match result {
+                    Ok(height) => height,
+                    Err(err) => {
+                        errors.push(err);
+                        continue;
+                    }
+                };
 
-                if block.size.x > 1 || block.size.y > 1 {
-                    unimplemented!("Multiblock gadgets not supported!");
-                }
-
-                let base = if let Some(block) = ship
-                    .tiles
-                    .get(pos)
-                    .and_then(|tile| tile.block)
-                    .and_then(|entity| block_entities.get(entity))
-                    .map(|block_entity| blocks.get_block(block_entity.block_id))
-                {
-                    block
+                let block_entity = BlockEntity {
+                    block_id: *block_id,
+                    root: *pos,
+                    orientation: *orientation,
+                };
+                // Blocks are attached to the ship, and gadgets to the block below their root tile.
+                // The ship may have moved away from the origin, so everything is placed relative to it.
+                let local = block_entity.ship_transform(&blocks, height);
+                let transform = ship_transform.apply_to(&local);
+                let parent = if block.is_gadget {
+                    let base = ship.tiles[pos].block.unwrap();
+                    let base_local = block_entity_of(&built_blocks, base)
+                        .unwrap()
+                        .ship_transform(&blocks, 0.0);
+                    Parent {
+                        entity: base,
+                        local: local.relative_to(&base_local),
+                    }
                 } else {
-                    continue;
+                    Parent {
+                        entity: ship_entity,
+                        local,
+                    }
                 };
-
                 let entity_builder = lazy_update
                     .create_entity(&entities)
                     .with(Model::new(block.mesh_id))
-                    .with(Transform::from_position(
-                        pos.x as f32,
-                        pos.y as f32,
-                        base.height,
-                    ))
+                    .with(block_entity)
+                    .with(Health(block.health))
+                    .with(transform)
+                    .with(parent)
                     .with(Collider::new(
                         block.hitbox.clone(),
                         Collider::SHIP,
                         vec![Collider::ASTEROID],
                     ));
-                let entity = if let Some(setup) = block.setup {
-                    (setup)(entity_builder).build()
+                let entity_builder = if block.power_usage > 0.0 {
+                    entity_builder.with(PowerConsumer::default())
                 } else {
-                    entity_builder.build()
+                    entity_builder
                 };
+                let entity_builder = if block.battery_capacity > 0.0 {
+                    entity_builder.with(Battery::default())
+                } else {
+                    entity_builder
+                };
+                let entity_builder = if block.needs_operator {
+                    entity_builder.with(OperatorStation::default())
+                } else {
+                    entity_builder
+                };
+                let entity = block
+                    .setup
+                    .iter()
+                    .fold(entity_builder, |builder, setup| (setup)(builder))
+                    .build();
+                built_blocks.insert(entity, block_entity);
+                built.push((entity, block_entity));
 
-                ship.tiles
-                    .get_mut(pos)
-                    .expect("Placed block outside ship boundries")
-                    .gadget = Some(entity);
-            }
-            BuildAction::BuildBlock(pos, block_id) => {
-                let block = blocks.get_block(*block_id);
-
-                if block.size.x > 1 || block.size.y > 1 {
-                    unimplemented!("Multiblock sizes not implemented!");
+                for pos in tiles {
+                    let tile = ship.tile_mut(pos);
+                    if block.is_gadget {
+                        tile.gadget = Some(entity);
+                    } else {
+                        tile.block = Some(entity);
+                    }
                 }
 
-                let entity_builder = lazy_update
-                    .create_entity(&entities)
-                    .with(Model::new(block.mesh_id))
-                    .with(BlockEntity {
-                        block_id: *block_id,
-                        root: *pos,
-                    })
-                    .with(Transform::from_position(pos.x as f32, pos.y as f32, 0.0))
-                    .with(Collider::new(
-                        block.hitbox.clone(),
-                        Collider::SHIP,
-                        vec![Collider::ASTEROID],
-                    ));
-                let block_entity = if let Some(setup) = block.setup {
-                    (setup)(entity_builder).build()
+                inverses.push(vec![if block.is_gadget {
+                    BuildAction::RemoveGadget(*pos)
                 } else {
-                    entity_builder.build()
-                };
-
-                ship.tiles
-                    .get_mut(pos)
-                    .expect("Placed block outside ship boundries")
-                    .block = Some(block_entity);
+                    BuildAction::RemoveBlock(*pos)
+                }]);
             }
             BuildAction::BuildFloor(pos, floor) => {
+                if let Err(err) = ship.check_floor(*pos) {
+                    errors.push(err);
+                    continue;
+                }
+
+                let local = Transform::from_position(pos.x as f32, pos.y as f32, 0.0);
                 let tile_entity = lazy_update
                     .create_entity(&entities)
                     .with(Model::new((*floor).into()))
-                    .with(Transform::from_position(pos.x as f32, pos.y as f32, 0.0))
+                    .with(FloorEntity(*floor))
+                    .with(ship_transform.apply_to(&local))
+                    .with(Parent {
+                        entity: ship_entity,
+                        local,
+                    })
                     .build();
+                built_floors.insert(tile_entity, *floor);
+                ship.tile_mut(*pos).floor = Some(tile_entity);
 
-                ship.tiles
-                    .get_mut(pos)
-                    .expect("Placed floor outside ship boundries")
-                    .block = Some(tile_entity);
+                inverses.push(vec![BuildAction::RemoveFloor(*pos)]);
+            }
+            BuildAction::RemoveBlock(pos) => {
+                let block = match ship.tiles.get(pos).and_then(|tile| tile.block) {
+                    Some(block) => block,
+                    None => continue,
+                };
+
+                // The block has to be rebuilt before the gadgets that sat on it
+                let mut inverse = Vec::new();
+                for entity in ship.remove_block(block) {
+                    if let Some(block_entity) = block_entity_of(&built_blocks, entity) {
+                        inverse.push(remove_block_entity(&mut inventory, block_entity));
+                    }
+                    to_be_removed.add(entity);
+                }
+                inverses.push(inverse);
+            }
+            BuildAction::RemoveGadget(pos) => {
+                if let Some(gadget) = ship.tiles.get(pos).and_then(|tile| tile.gadget) {
+                    ship.clear_entity(gadget);
+                    if let Some(block_entity) = block_entity_of(&built_blocks, gadget) {
+                        inverses.push(vec![remove_block_entity(&mut inventory, block_entity)]);
+                    }
+                    to_be_removed.add(gadget);
+                }
+            }
+            BuildAction::RemoveFloor(pos) => {
+                if let Some(entity) = ship.tiles.get(pos).and_then(|tile| tile.floor) {
+                    ship.clear_entity(entity);
+                    ship.structure_changed = true;
+                    let floor = floor_entities
+                        .get(entity)
+                        .map(|floor| floor.0)
+                        .or_else(|| built_floors.get(&entity).copied());
+                    if let Some(floor) = floor {
+                        inverses.push(vec![BuildAction::BuildFloor(*pos, floor)]);
+                    }
+                    to_be_removed.add(entity);
+                }
             }
-            _ => unimplemented!(),
         }
     }
-}
 
-pub fn create_ship(world: &mut World) {
-    let mut tiles = HashMap::new();
-    let initial_size = 32;
-    for x in -initial_size..initial_size {
-        for y in -initial_size..initial_size {
-            tiles.insert(
-                (x, y).into(),
-                Tile {
-                    block: None,
-                    gadget: None,
-                    floor: None,
-                },
-            );
-        }
+    BuildOutcome {
+        errors,
+        inverse: inverses.into_iter().rev().flatten().collect(),
+        built,
     }
+}
 
-    let ship = world.create_entity().with(Ship { tiles }).build();
-    let (ship_build_actions, ship_build_gadgets) = build_initial_ship(&world);
+/// Creates the player's starting ship from the default blueprint
+pub fn create_ship(world: &mut World) -> Entity {
+    let blueprint = Blueprint::load(DEFAULT_BLUEPRINT_PATH)
+        .unwrap_or_else(|err| panic!("Unable to load {}: {}", DEFAULT_BLUEPRINT_PATH, err));
+    let actions = blueprint
+        .build_actions(world, &Ship::empty(), Vector2::new(0, 0))
+        .unwrap_or_else(|err| panic!("Unable to build {}: {}", DEFAULT_BLUEPRINT_PATH, err));
 
-    execute_build_actions(world, ship, &ship_build_actions);
+    let ship = create_empty_ship(world);
+    execute_build_actions(world, ship, &actions, Payment::Free);
     // execute_build_actions adds the entities lazily, so we need to maintain the world
     // in order to add the block entities
     world.maintain();
-    execute_build_actions(world, ship, &ship_build_gadgets);
+
+    ship
 }
 
-fn build_initial_ship(world: &World) -> (Vec<BuildAction>, Vec<BuildAction>) {
-    let blocks = world.fetch::<Blocks>();
-    let floors = world.fetch::<Floors>();
-
-    let mut ship = Vec::new();
-    let mut gadgets = Vec::new();
-    let size = 7;
-
-    for x in 0..=size {
-        for y in 0..=size {
-            if x == 0 || y == 0 || x == size || y == size {
-                ship.push(BuildAction::BuildBlock(Point2::new(x, y), blocks.wall));
-            } else if x == 3 && y == 3 {
-                ship.push(BuildAction::BuildFloor(Point2::new(3, 3), floors.dirt));
-            } else {
-                ship.push(BuildAction::BuildFloor(Point2::new(x, y), floors.metal));
-            }
-        }
-    }
-    ship.push(BuildAction::BuildBlock(
-        Point2::new(size + 1, -2),
-        blocks.engine,
-    ));
-    ship.push(BuildAction::BuildBlock(
-        Point2::new(size + 1, size + 2),
-        blocks.engine,
-    ));
-    ship.push(BuildAction::BuildBlock(Point2::new(size, -2), blocks.cube));
-    ship.push(BuildAction::BuildBlock(Point2::new(size, -1), blocks.cube));
-    ship.push(BuildAction::BuildBlock(
-        Point2::new(size, size + 1),
-        blocks.cube,
-    ));
-    ship.push(BuildAction::BuildBlock(
-        Point2::new(size, size + 2),
-        blocks.cube,
-    ));
-    ship.push(BuildAction::BuildBlock(
-        Point2::new(-1, size / 2),
-        blocks.miner,
-    ));
-
-    gadgets.push(BuildAction::BuildBlock(Point2::new(-5, 5), blocks.laser));
-    gadgets.push(BuildAction::BuildBlock(Point2::new(0, 0), blocks.laser));
-
-    (ship, gadgets)
+/// Creates a ship without any blocks or floors, resting at the origin
+pub fn create_empty_ship(world: &mut World) -> Entity {
+    world
+        .create_entity()
+        .with(Ship::empty())
+        .with(Transform::from_position(0.0, 0.0, 0.0))
+        .with(RigidBody {
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+        })
+        .with(ShipBody::default())
+        .with(Atmosphere::default())
+        .with(JobQueue::default())
+        .build()
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// Builds a ship out of rows of tiles, starting with the row at y = 0. Each tile is
+    /// empty (` `), a floor (`.`), a block (`#`), or a block standing on a floor (`D`).
+    /// Every block and floor gets its own entity.
+    pub fn ship_from_layout(world: &mut World, layout: &[&str]) -> Ship {
+        let mut ship = Ship::empty();
+        for (y, row) in layout.iter().enumerate() {
+            for (x, kind) in row.chars().enumerate() {
+                let pos = Point2::new(x as i16, y as i16);
+                if kind == '.' || kind == 'D' {
+                    ship.tile_mut(pos).floor = Some(world.create_entity().build());
+                }
+                if kind == '#' || kind == 'D' {
+                    ship.tile_mut(pos).block = Some(world.create_entity().build());
+                }
+            }
+        }
+        ship
+    }
+
+    #[test]
+    fn find_path_is_empty_when_already_at_the_goal() {
+        let mut world = World::new();
+        let ship = ship_from_layout(&mut world, &["..."]);
+
+        assert_eq!(
+            ship.find_path(Point2::new(1, 0), Point2::new(1, 0), |_| false),
+            Some(Vec::new())
+        );
+    }
+
+    fn points(points: &[(i16, i16)]) -> Vec<Point2<i16>> {
+        points.iter().map(|(x, y)| Point2::new(*x, *y)).collect()
+    }
+
+    #[test]
+    fn find_path_goes_around_blocks() {
+        let mut world = World::new();
+        let ship = ship_from_layout(&mut world, &[".#...", ".#.#.", "...#."]);
+
+        let path = ship.find_path(Point2::new(0, 0), Point2::new(4, 0), |_| false);
+
+        assert_eq!(
+            path,
+            Some(points(&[
+                (0, 1),
+                (0, 2),
+                (1, 2),
+                (2, 2),
+                (2, 1),
+                (2, 0),
+                (3, 0),
+                (4, 0)
+            ]))
+        );
+    }
+
+    #[test]
+    fn find_path_fails_when_the_goal_cant_be_reached() {
+        let mut world = World::new();
+        let ship = ship_from_layout(&mut world, &["..#.", "..# "]);
+
+        assert_eq!(
+            ship.find_path(Point2::new(0, 0), Point2::new(3, 0), |_| false),
+            None
+        );
+        assert_eq!(
+            ship.find_path(Point2::new(0, 0), Point2::new(2, 0), |_| false),
+            None
+        );
+        assert_eq!(
+            ship.find_path(Point2::new(0, 0), Point2::new(3, 1), |_| false),
+            None
+        );
+    }
+
+    #[test]
+    fn find_path_only_crosses_passable_blocks() {
+        let mut world = World::new();
+        let ship = ship_from_layout(&mut world, &[".D."]);
+        let door = ship.tile(Point2::new(1, 0)).and_then(Tile::block).unwrap();
+
+        assert_eq!(
+            ship.find_path(Point2::new(0, 0), Point2::new(2, 0), |_| false),
+            None
+        );
+        assert_eq!(
+            ship.find_path(Point2::new(0, 0), Point2::new(2, 0), |entity| entity
+                == door),
+            Some(points(&[(1, 0), (2, 0)]))
+        );
+    }
+
+    #[test]
+    fn find_detached_finds_groups_cut_off_from_the_cores() {
+        let mut world = World::new();
+        let ship = ship_from_layout(&mut world, &["#.. ##", "     .", "#"]);
+
+        let mut detached = ship.find_detached(&points(&[(0, 0)]));
+        for group in &mut detached {
+            group.sort_by_key(|pos| (pos.x, pos.y));
+        }
+
+        assert_eq!(
+            detached,
+            vec![points(&[(0, 2)]), points(&[(4, 0), (5, 0), (5, 1)])]
+        );
+    }
+
+    #[test]
+    fn find_detached_finds_nothing_when_every_tile_reaches_a_core() {
+        let mut world = World::new();
+        let ship = ship_from_layout(&mut world, &["#..", "  .", "#.."]);
+
+        assert!(ship.find_detached(&points(&[(0, 0)])).is_empty());
+    }
+
+    #[test]
+    fn detach_moves_the_tiles_into_a_new_ship() {
+        let mut world = World::new();
+        let mut ship = ship_from_layout(&mut world, &["#.  ##"]);
+
+        let debris = ship.detach(&points(&[(4, 0), (5, 0)]));
+
+        assert_eq!(ship.tiles().count(), 2);
+        assert_eq!(debris.tiles().count(), 2);
+        assert!(debris.tile(Point2::new(4, 0)).is_some());
+        assert!(ship.tile(Point2::new(4, 0)).is_none());
+    }
 }
@@ -0,0 +1,176 @@
+use super::{
+    movement::ShipBody, objects::Health, BlockEntity, Parent, RigidBody, Ship, ToBeRemoved,
+    Transform,
+};
+use crate::block::Blocks;
+use cgmath::{prelude::*, Point2, Vector2, Vector3};
+use specs::{prelude::*, Component};
+
+pub fn register_components(world: &mut World) {
+    world.register::<Debris>();
+}
+
+pub fn setup_systems(builder: &mut DispatcherBuilder) {
+    builder.add(DestroyedBlockSystem, "destroyed_block_system", &[]);
+    builder.add(
+        StructureSystem,
+        "structure_system",
+        &["destroyed_block_system"],
+    );
+}
+
+/// Marks a ship made of the pieces that broke away from another ship
+#[derive(Component, Default)]
+#[storage(NullStorage)]
+pub struct Debris;
+
+/// Removes blocks (and the gadgets on top of them) from their ship once they run out of health
+pub struct DestroyedBlockSystem;
+
+impl<'a> System<'a> for DestroyedBlockSystem {
+    type SystemData = (
+        Write<'a, ToBeRemoved>,
+        WriteStorage<'a, Ship>,
+        ReadStorage<'a, Health>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut to_be_removed, mut ships, healths) = data;
+
+        for ship in (&mut ships).join() {
+            let destroyed: Vec<Entity> = ship
+                .entities()
+                .into_iter()
+                .filter(|entity| {
+                    healths
+                        .get(*entity)
+                        .is_some_and(|health| health.health() == 0)
+                })
+                .collect();
+
+            for entity in destroyed {
+                for removed in ship.remove_block(entity) {
+                    to_be_removed.add(removed);
+                }
+            }
+        }
+    }
+}
+
+/// Breaks off the parts of ships that are no longer connected to a core block.
+/// Ships without a core are left alone, since there is nothing to measure against.
+/// Debris starts out moving and turning along with the ship that it broke off of.
+pub struct StructureSystem;
+
+impl StructureSystem {
+    /// How fast debris drifts away from the ship that it broke off of
+    const DEBRIS_SPEED: f32 = 0.5;
+}
+
+impl<'a> System<'a> for StructureSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, LazyUpdate>,
+        ReadExpect<'a, Blocks>,
+        WriteStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, RigidBody>,
+        ReadStorage<'a, ShipBody>,
+        ReadStorage<'a, Transform>,
+        WriteStorage<'a, Parent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            lazy_update,
+            blocks,
+            mut ships,
+            block_entities,
+            bodies,
+            ship_bodies,
+            transforms,
+            mut parents,
+        ) = data;
+        let is_core = |entity| {
+            block_entities
+                .get(entity)
+                .is_some_and(|block| blocks.get_block(block.block_id()).is_core)
+        };
+
+        let mut debris = Vec::new();
+        for (ship_entity, ship) in (&entities, &mut ships).join() {
+            if !ship.take_structure_changed() {
+                continue;
+            }
+
+            let cores = ship.core_tiles(is_core);
+            if cores.is_empty() {
+                continue;
+            }
+
+            let transform = transforms
+                .get(ship_entity)
+                .cloned()
+                .unwrap_or_else(|| Transform::from_position(0.0, 0.0, 0.0));
+            let ship_velocity = bodies
+                .get(ship_entity)
+                .map_or(Vector3::zero(), |body| body.velocity);
+            let ship_body = ship_bodies.get(ship_entity).copied().unwrap_or_default();
+
+            let core_center = center(&cores);
+            for island in ship.find_detached(&cores) {
+                // Push the debris away from the core, so that it doesn't sit inside the ship
+                let direction = center(&island) - core_center;
+                let direction = if direction.magnitude2() > 0.0 {
+                    direction.normalize()
+                } else {
+                    Vector2::unit_x()
+                };
+                let drift = direction.extend(0.0) * Self::DEBRIS_SPEED;
+                let velocity = ship_velocity + transform.rotation.rotate_vector(drift);
+
+                debris.push((
+                    ship.detach(&island),
+                    ship_entity,
+                    transform.clone(),
+                    velocity,
+                    ship_body,
+                ));
+            }
+        }
+
+        for (ship, old_ship, transform, velocity, ship_body) in debris {
+            // The pieces keep their place on the grid, which now belongs to the debris.
+            // Gadgets whose base block stayed behind are attached to the debris itself.
+            let debris_entity = entities.create();
+            let pieces = ship.entities();
+            for entity in &pieces {
+                if let Some(parent) = parents.get_mut(*entity) {
+                    if parent.entity == old_ship {
+                        parent.entity = debris_entity;
+                    } else if !pieces.contains(&parent.entity) {
+                        if let Some(world) = transforms.get(*entity) {
+                            parent.local = world.relative_to(&transform);
+                        }
+                        parent.entity = debris_entity;
+                    }
+                }
+            }
+
+            lazy_update.insert(debris_entity, ship);
+            lazy_update.insert(debris_entity, Debris);
+            lazy_update.insert(debris_entity, transform);
+            lazy_update.insert(debris_entity, RigidBody { velocity });
+            lazy_update.insert(debris_entity, ship_body);
+        }
+    }
+}
+
+/// The average position of the tiles
+fn center(tiles: &[Point2<i16>]) -> Vector2<f32> {
+    let sum = tiles.iter().fold(Vector2::zero(), |sum, pos| {
+        sum + Vector2::new(pos.x as f32, pos.y as f32)
+    });
+    sum / tiles.len() as f32
+}
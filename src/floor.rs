@@ -2,11 +2,14 @@ use crate::graphics::{Mesh, MeshId, MeshManager};
 use cgmath::Point3;
 
 #[derive(Clone, Copy)]
-pub struct Floor(MeshId);
+pub struct Floor {
+    pub name: &'static str,
+    mesh_id: MeshId,
+}
 
 impl Into<MeshId> for Floor {
     fn into(self) -> MeshId {
-        self.0
+        self.mesh_id
     }
 }
 
@@ -15,15 +18,35 @@ pub struct Floors {
     pub dirt: Floor,
 }
 
-pub fn load_floors(device: &wgpu::Device, mesh_manager: &mut MeshManager) -> Floors {
+impl Floors {
+    pub fn iter(&self) -> impl Iterator<Item = Floor> {
+        vec![self.metal, self.dirt].into_iter()
+    }
+
+    pub fn get_floor(&self, name: &str) -> Option<Floor> {
+        self.iter().find(|floor| floor.name == name)
+    }
+}
+
+pub fn load_floors(mesh_manager: &mut MeshManager) -> Floors {
     Floors {
-        metal: Floor(mesh_manager.add(
-            device,
-            &Mesh::rectangular_prism(1.0, 1.0, 0.1, Point3::new(0.9, 0.9, 1.0)),
-        )),
-        dirt: Floor(mesh_manager.add(
-            device,
-            &Mesh::rectangular_prism(1.0, 1.0, 0.1, Point3::new(0.14, 0.08, 0.08)),
-        )),
+        metal: Floor {
+            name: "metal",
+            mesh_id: mesh_manager.add(&Mesh::rectangular_prism(
+                1.0,
+                1.0,
+                0.1,
+                Point3::new(0.9, 0.9, 1.0),
+            )),
+        },
+        dirt: Floor {
+            name: "dirt",
+            mesh_id: mesh_manager.add(&Mesh::rectangular_prism(
+                1.0,
+                1.0,
+                0.1,
+                Point3::new(0.14, 0.08, 0.08),
+            )),
+        },
     }
 }
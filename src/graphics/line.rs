@@ -7,7 +7,7 @@ pub struct LineRenderer {
 }
 
 impl LineRenderer {
-    const MAX_LINES: u64 = 32;
+    pub const MAX_LINES: u64 = 1024;
 
     pub fn new(
         device: &wgpu::Device,
@@ -76,6 +76,22 @@ impl Mesh {
         }
     }
 
+    /// Scales the mesh along each axis (relative to the origin)
+    pub fn scale(&mut self, x: f32, y: f32, z: f32) {
+        for vertex in &mut self.vertices {
+            vertex.pos.x *= x;
+            vertex.pos.y *= y;
+            vertex.pos.z *= z;
+        }
+    }
+
+    /// Blends the color of every vertex halfway towards the given color
+    pub fn tint(&mut self, c: Point3<f32>) {
+        for vertex in &mut self.vertices {
+            vertex.color = vertex.color.midpoint(c);
+        }
+    }
+
     pub fn recolor(&mut self, c: Point3<f32>) {
         for vertex in &mut self.vertices {
             vertex.color.x += c.x;
@@ -113,16 +129,29 @@ struct ModelMatrix(Matrix4<f32>);
 unsafe impl bytemuck::Pod for ModelMatrix {}
 unsafe impl bytemuck::Zeroable for ModelMatrix {}
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct MeshId(usize);
 
 pub type ModelId = generational_arena::Index;
 
+/// Keeps track of every registered mesh and the models (instances) of each mesh.
+///
+/// Meshes are only uploaded to the GPU when the renderer asks for them, so the
+/// mesh manager (and the ECS that owns it) can be used without a wgpu device.
 pub struct MeshManager {
-    meshes: Vec<GPUMesh>,
+    meshes: Vec<MeshSlot>,
     models: Vec<Arena<Matrix4<f32>>>,
 }
 
+struct MeshSlot {
+    /// The CPU side copy of the mesh, kept so that variants of it can be created later
+    mesh: Mesh,
+    gpu_mesh: Option<GPUMesh>,
+    visible: bool,
+    /// Translucent meshes are drawn after everything else and don't write to the depth buffer
+    translucent: bool,
+}
+
 impl MeshManager {
     pub fn new() -> MeshManager {
         MeshManager {
@@ -131,10 +160,22 @@ impl MeshManager {
         }
     }
 
-    pub fn add(&mut self, device: &wgpu::Device, mesh: &Mesh) -> MeshId {
+    pub fn add(&mut self, mesh: &Mesh) -> MeshId {
+        self.add_slot(mesh, false)
+    }
+
+    pub fn add_translucent(&mut self, mesh: &Mesh) -> MeshId {
+        self.add_slot(mesh, true)
+    }
+
+    fn add_slot(&mut self, mesh: &Mesh, translucent: bool) -> MeshId {
         let id = self.meshes.len();
-        let gpu_mesh = GPUMesh::create(device, mesh, id);
-        self.meshes.push(gpu_mesh);
+        self.meshes.push(MeshSlot {
+            mesh: mesh.clone(),
+            gpu_mesh: None,
+            visible: true,
+            translucent,
+        });
         self.models.push(Arena::new());
 
         println!("[Registered Mesh] {}={}", &mesh.name, id);
@@ -142,6 +183,14 @@ impl MeshManager {
         MeshId(id)
     }
 
+    pub fn get_mesh(&self, mesh_id: MeshId) -> &Mesh {
+        &self
+            .meshes
+            .get(mesh_id.0)
+            .unwrap_or_else(|| panic!("Invalid mesh ID: {}", mesh_id.0))
+            .mesh
+    }
+
     pub fn new_model(&mut self, mesh: MeshId, model: Matrix4<f32>) -> ModelId {
         let arena = self
             .models
@@ -167,8 +216,26 @@ impl MeshManager {
         arena.remove(model_id).expect("Invalid model ID!");
     }
 
+    /// The total number of models across every mesh
+    pub fn model_count(&self) -> usize {
+        self.models.iter().map(|arena| arena.len()).sum()
+    }
+
+    /// Creates GPU buffers for every mesh that has been added since the last upload
+    pub fn upload_meshes(&mut self, device: &wgpu::Device) {
+        for (index, slot) in self.meshes.iter_mut().enumerate() {
+            if slot.gpu_mesh.is_none() {
+                slot.gpu_mesh = Some(GPUMesh::create(device, &slot.mesh, index));
+            }
+        }
+    }
+
     fn push_meshes_to_gpu(&mut self, queue: &wgpu::Queue) {
-        for (index, mesh) in &mut self.meshes.iter_mut().enumerate() {
+        for (index, slot) in &mut self.meshes.iter_mut().enumerate() {
+            let mesh = match &mut slot.gpu_mesh {
+                Some(mesh) => mesh,
+                None => continue,
+            };
             let models = self
                 .models
                 .get(index)
@@ -201,7 +268,6 @@ struct GPUMesh {
     index_count: u32,
     models_buffer: wgpu::Buffer,
     instances: u32,
-    visible: bool,
 }
 
 impl GPUMesh {
@@ -232,7 +298,6 @@ impl GPUMesh {
             models_buffer,
             index_count: mesh.indices.len() as u32,
             instances: 0,
-            visible: true,
         }
     }
 }
@@ -241,6 +306,7 @@ pub struct Renderer {
     pub ui_renderer: UiRenderer,
     line_renderer: LineRenderer,
     pipeline: wgpu::RenderPipeline,
+    translucent_pipeline: wgpu::RenderPipeline,
     camera_bg: wgpu::BindGroup,
     camera_buffer: wgpu::Buffer,
     depth_texture: GPUTexture,
@@ -309,7 +375,19 @@ impl Renderer {
             push_constant_ranges: &[],
         });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        // Translucent meshes are blended with the scene using the pass's blend color
+        let create_pipeline = |translucent: bool| {
+            let color_blend = if translucent {
+                wgpu::BlendState {
+                    src_factor: wgpu::BlendFactor::BlendColor,
+                    dst_factor: wgpu::BlendFactor::OneMinusBlendColor,
+                    operation: wgpu::BlendOperation::Add,
+                }
+            } else {
+                wgpu::BlendState::default()
+            };
+
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
             layout: Some(&pipeline_layout),
             primitive: wgpu::PrimitiveState {
@@ -330,7 +408,7 @@ impl Renderer {
             },
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: Renderer::DEPTH_FORMAT,
-                depth_write_enabled: true,
+                depth_write_enabled: !translucent,
                 depth_compare: wgpu::CompareFunction::Less,
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
@@ -356,18 +434,22 @@ impl Renderer {
                 entry_point: "main",
                 targets: &[wgpu::ColorTargetState {
                     format: swapchain.format,
-                    color_blend: wgpu::BlendState::default(),
+                    color_blend,
                     alpha_blend: wgpu::BlendState::default(),
                     write_mask: wgpu::ColorWrite::ALL,
                 }]
             }),
-        });
+        })
+        };
+        let pipeline = create_pipeline(false);
+        let translucent_pipeline = create_pipeline(true);
 
         let line_renderer = LineRenderer::new(device, &camera_bgl, swapchain);
         let ui_renderer = UiRenderer::new(device, swapchain);
 
         Renderer {
             pipeline,
+            translucent_pipeline,
             camera_bg,
             camera_buffer,
             depth_texture,
@@ -386,6 +468,8 @@ impl Renderer {
         mesh_manager: &mut MeshManager,
         lines: &[Line],
     ) {
+        // Anything past the end of the line buffer isn't drawn
+        let lines = &lines[..lines.len().min(LineRenderer::MAX_LINES as usize)];
         queue.write_buffer(
             &self.line_renderer.vertex_buffer,
             0,
@@ -425,21 +509,41 @@ impl Renderer {
         rpass.set_pipeline(&self.pipeline);
         rpass.set_bind_group(0, &self.camera_bg, &[]);
 
-        mesh_manager
-            .meshes
-            .iter()
-            .filter(|mesh| mesh.visible)
-            .for_each(|mesh| {
-                rpass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-                rpass.set_vertex_buffer(1, mesh.models_buffer.slice(..));
-                rpass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                rpass.draw_indexed(0..mesh.index_count, 0, 0..mesh.instances);
-            });
+        let mesh_manager = &*mesh_manager;
+        let meshes = |translucent: bool| {
+            mesh_manager
+                .meshes
+                .iter()
+                .filter(move |slot| slot.visible && slot.translucent == translucent)
+                .filter_map(|slot| slot.gpu_mesh.as_ref())
+        };
+
+        for mesh in meshes(false) {
+            rpass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            rpass.set_vertex_buffer(1, mesh.models_buffer.slice(..));
+            rpass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            rpass.draw_indexed(0..mesh.index_count, 0, 0..mesh.instances);
+        }
 
         rpass.set_pipeline(&self.line_renderer.pipeline);
         rpass.set_vertex_buffer(0, self.line_renderer.vertex_buffer.slice(..));
         rpass.draw(0..2, 0..lines.len() as u32);
 
+        // Translucent meshes go last so that everything behind them has already been drawn
+        rpass.set_pipeline(&self.translucent_pipeline);
+        rpass.set_blend_color(wgpu::Color {
+            r: 0.5,
+            g: 0.5,
+            b: 0.5,
+            a: 0.5,
+        });
+        for mesh in meshes(true) {
+            rpass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            rpass.set_vertex_buffer(1, mesh.models_buffer.slice(..));
+            rpass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            rpass.draw_indexed(0..mesh.index_count, 0, 0..mesh.instances);
+        }
+
         std::mem::drop(rpass);
     }
 
@@ -558,6 +662,18 @@ impl Camera {
         0.0, 0.0, 0.5, 1.0,
     );
 
+    pub fn new(aspect: f32) -> Self {
+        Self {
+            position: (-18.0, 0.0, 18.0).into(),
+            yaw: 0.0,
+            pitch: -1.3,
+            aspect,
+            fov: 45.0,
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+
     pub fn resize(&mut self, swapchain: &wgpu::SwapChainDescriptor) {
         self.aspect = swapchain.width as f32 / swapchain.height as f32;
     }
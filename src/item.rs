@@ -1,9 +1,11 @@
 use crate::graphics::{TextureAtlas, TextureRegion2D};
 use cgmath::Point3;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum GameItem {
     Iron,
     Copper,
@@ -16,6 +18,14 @@ impl GameItem {
         VARIANTS.iter()
     }
 
+    /// How much a single item is worth, which makes rarer ores worth going after first
+    pub fn value(self) -> u32 {
+        match self {
+            GameItem::Iron => 1,
+            GameItem::Copper => 2,
+        }
+    }
+
     pub fn asteroid_info() -> Vec<(GameItem, Point3<f32>)> {
         vec![
             (GameItem::Iron, Point3::new(0.15, 0.0, 0.0)),
@@ -39,6 +49,26 @@ pub fn load_item_icons(atlas: &mut TextureAtlas) -> HashMap<GameItem, TextureReg
     map
 }
 
+/// Returned when the inventory doesn't hold enough of an item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotEnoughItems {
+    pub item: GameItem,
+    pub needed: u32,
+    pub available: u32,
+}
+
+impl fmt::Display for NotEnoughItems {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Not enough {:?} (needed {}, but only have {})",
+            self.item, self.needed, self.available
+        )
+    }
+}
+
+impl std::error::Error for NotEnoughItems {}
+
 pub struct Inventory {
     items: HashMap<GameItem, u32>,
 }
@@ -53,10 +83,55 @@ impl Inventory {
         Inventory { items }
     }
 
+    pub fn from_items(items: HashMap<GameItem, u32>) -> Self {
+        let items = GameItem::iter()
+            .map(|item| (*item, items.get(item).copied().unwrap_or(0)))
+            .collect();
+
+        Inventory { items }
+    }
+
+    pub fn items(&self) -> &HashMap<GameItem, u32> {
+        &self.items
+    }
+
     pub fn change_amount(&mut self, item: GameItem, delta: u32) {
         self.items.entry(item).and_modify(|amount| *amount += delta);
     }
 
+    /// Checks that every item in the list could be removed at the same time
+    pub fn check_items(&self, items: &[(GameItem, u32)]) -> Result<(), NotEnoughItems> {
+        for (item, _) in items {
+            let needed = items
+                .iter()
+                .filter(|(other, _)| other == item)
+                .map(|(_, amount)| amount)
+                .sum();
+            let available = self.amount(item);
+
+            if needed > available {
+                return Err(NotEnoughItems {
+                    item: *item,
+                    needed,
+                    available,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes every item in the list, or none of them if there isn't enough of any item
+    pub fn remove_items(&mut self, items: &[(GameItem, u32)]) -> Result<(), NotEnoughItems> {
+        self.check_items(items)?;
+
+        for (item, amount) in items {
+            self.items.entry(*item).and_modify(|total| *total -= amount);
+        }
+
+        Ok(())
+    }
+
     pub fn amount(&self, item: &GameItem) -> u32 {
         *self
             .items
@@ -64,3 +139,59 @@ impl Inventory {
             .expect("Item is a not a valid variant!")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inventory(iron: u32, copper: u32) -> Inventory {
+        Inventory::from_items(
+            vec![(GameItem::Iron, iron), (GameItem::Copper, copper)]
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn remove_items_takes_every_item() {
+        let mut inventory = inventory(5, 3);
+
+        assert_eq!(
+            inventory.remove_items(&[(GameItem::Iron, 2), (GameItem::Copper, 3)]),
+            Ok(())
+        );
+        assert_eq!(inventory.amount(&GameItem::Iron), 3);
+        assert_eq!(inventory.amount(&GameItem::Copper), 0);
+    }
+
+    #[test]
+    fn remove_items_takes_nothing_when_any_item_is_short() {
+        let mut inventory = inventory(5, 1);
+
+        assert_eq!(
+            inventory.remove_items(&[(GameItem::Iron, 2), (GameItem::Copper, 3)]),
+            Err(NotEnoughItems {
+                item: GameItem::Copper,
+                needed: 3,
+                available: 1,
+            })
+        );
+        assert_eq!(inventory.amount(&GameItem::Iron), 5);
+        assert_eq!(inventory.amount(&GameItem::Copper), 1);
+    }
+
+    #[test]
+    fn remove_items_adds_up_repeated_items() {
+        let mut inventory = inventory(5, 0);
+
+        assert_eq!(
+            inventory.remove_items(&[(GameItem::Iron, 3), (GameItem::Iron, 3)]),
+            Err(NotEnoughItems {
+                item: GameItem::Iron,
+                needed: 6,
+                available: 5,
+            })
+        );
+        assert_eq!(inventory.amount(&GameItem::Iron), 5);
+    }
+}
@@ -13,8 +13,10 @@ pub const RENDER_HITBOXES: bool = false;
 pub const RENDER_BLOCKS: bool = true;
 pub const MSAA_SAMPLE: u32 = 4; //TODO - determine this dynamically
 pub const PI: f32 = std::f32::consts::PI;
+pub const QUICKSAVE_PATH: &str = "saves/quicksave.ron";
 
 mod app;
+mod bench;
 mod block;
 mod entity;
 mod floor;
@@ -36,25 +38,17 @@ impl app::Application for AppState {
     ) -> Self {
         let mut mesh_manager = MeshManager::new();
         let mut renderer = Renderer::new(device, &swapchain);
-        let blocks = block::load_blocks(device, &mut mesh_manager);
-        let floors = floor::load_floors(device, &mut mesh_manager);
-        let camera = Camera {
-            position: (-18.0, 0.0, 18.0).into(),
-            yaw: 0.0,
-            pitch: -1.3,
-            aspect: swapchain.width as f32 / swapchain.height as f32,
-            fov: 45.0,
-            near: 0.1,
-            far: 100.0,
-        };
+        let blocks = block::load_blocks(&mut mesh_manager);
+        let floors = floor::load_floors(&mut mesh_manager);
+        let camera = Camera::new(swapchain.width as f32 / swapchain.height as f32);
         let window_size = WindowSize {
             width: swapchain.width as f32,
             height: swapchain.height as f32,
         };
 
-        let ecs = ECS::new(device, mesh_manager, blocks, floors, camera, window_size);
+        let ecs = ECS::new(mesh_manager, blocks, floors, camera, window_size);
         let ui_assets = UiAssets::new(device, queue, &mut renderer.ui_renderer.batch.atlas);
-        let ui = Ui::new(ui_assets);
+        let ui = Ui::new(ui_assets, &ecs);
         queue.submit(None);
 
         AppState { renderer, ecs, ui }
@@ -75,6 +69,32 @@ impl app::Application for AppState {
     }
 
     fn key_event(&mut self, key: event::VirtualKeyCode, state: event::ElementState) {
+        if state == event::ElementState::Pressed {
+            let ctrl = {
+                let keys = &self.ecs.get_resource::<InputManager>().keys;
+                keys.is_key_down(event::VirtualKeyCode::LControl)
+                    || keys.is_key_down(event::VirtualKeyCode::RControl)
+            };
+
+            match key {
+                event::VirtualKeyCode::Z if ctrl => self.ecs.undo_build(),
+                event::VirtualKeyCode::Y if ctrl => self.ecs.redo_build(),
+                event::VirtualKeyCode::F3 => {
+                    let mut overlay = self.ecs.get_resource_mut::<entity::DebugOverlay>();
+                    overlay.show_rooms = !overlay.show_rooms;
+                }
+                event::VirtualKeyCode::F5 => match self.ecs.save(QUICKSAVE_PATH) {
+                    Ok(()) => println!("[Save] Saved game to {}", QUICKSAVE_PATH),
+                    Err(err) => println!("[Save] {}", err),
+                },
+                event::VirtualKeyCode::F9 => match self.ecs.load(QUICKSAVE_PATH) {
+                    Ok(()) => println!("[Save] Loaded game from {}", QUICKSAVE_PATH),
+                    Err(err) => println!("[Save] {}", err),
+                },
+                _ => {}
+            }
+        }
+
         self.ecs
             .get_resource_mut::<InputManager>()
             .keys
@@ -98,9 +118,14 @@ impl app::Application for AppState {
         let window_size = self.ecs.get_resource::<WindowSize>();
         pt.y = window_size.height - pt.y;
 
-        if !self.ui.on_click(button, state, pt) && button == event::MouseButton::Left {
+        if !self.ui.on_click(button, state, pt) {
             let mut input_manager = self.ecs.get_resource_mut::<InputManager>();
-            input_manager.left_mb = state == event::ElementState::Pressed;
+            let pressed = state == event::ElementState::Pressed;
+            match button {
+                event::MouseButton::Left => input_manager.left_mb = pressed,
+                event::MouseButton::Right => input_manager.right_mb = pressed,
+                _ => {}
+            }
         }
     }
 
@@ -123,8 +148,10 @@ impl app::Application for AppState {
         for (line, _) in (&lines_comps, &entities).join() {
             lines.push(*line);
         }
+        lines.extend(&self.ecs.get_resource::<entity::DebugOverlay>().lines);
 
         let mut mesh_manager = self.ecs.get_resource_mut::<MeshManager>();
+        mesh_manager.upload_meshes(device);
         self.ui.render(&mut self.renderer.ui_renderer.batch);
 
         let mut encoder =
@@ -145,7 +172,211 @@ impl app::Application for AppState {
 }
 
 fn main() {
-    app::run::<AppState>("Spaceship Alpha");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("--headless") => run_headless(&args[1..]),
+        Some("--bench") => bench::run_benchmarks(&args[1..]),
+        _ => app::run::<AppState>("Spaceship Alpha"),
+    }
+}
+
+/// Printed when the headless arguments can't be parsed
+const HEADLESS_USAGE: &str = "Usage: --headless [ticks] [--load <path>] [--ship-limit <radius>] \
+     [--stamp <path> <x> <y>] [--laser-targeting <Manual|Nearest|LowestHealth|MostValuable>] \
+     [--auto-mining] [--mining-salvo <size>] [--export-blueprint <path>] [--save <path>]";
+
+/// Steps the world without opening a window or creating a GPU device,
+/// then prints a summary of the resulting world state.
+///
+/// Usage: `--headless [ticks] [--load <path>] [--ship-limit <radius>] [--stamp <path> <x> <y>]
+/// [--laser-targeting <mode>] [--auto-mining] [--mining-salvo <size>] [--export-blueprint <path>]
+/// [--save <path>]`
+fn run_headless(args: &[String]) {
+    let ticks = args
+        .first()
+        .and_then(|ticks| ticks.parse().ok())
+        .unwrap_or(1000);
+    let option = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|index| args.get(index + 1))
+    };
+    let mut ecs = ECS::headless();
+
+    if let Some(path) = option("--load") {
+        ecs.load(path).expect("Unable to load save file");
+    }
+
+    if let Some(radius) = option("--ship-limit") {
+        let radius: i16 = radius.parse().unwrap_or_else(|_| exit_with_usage());
+        ecs.set_ship_limits(Some(entity::TileBounds::new(
+            cgmath::Point2::new(-radius, -radius),
+            cgmath::Point2::new(radius, radius),
+        )));
+    }
+
+    if let Some(index) = args.iter().position(|arg| arg == "--stamp") {
+        let stamp = &args[index + 1..];
+        let coordinate = |index: usize| {
+            stamp
+                .get(index)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or_else(|| exit_with_usage())
+        };
+        let path = stamp.first().unwrap_or_else(|| exit_with_usage());
+        let offset = cgmath::Vector2::new(coordinate(1), coordinate(2));
+        if let Err(err) = ecs.stamp_blueprint(path, offset) {
+            println!("[Headless] {}", err);
+        }
+    }
+
+    if let Some(mode) = option("--laser-targeting") {
+        let mode = ron::de::from_str(mode).unwrap_or_else(|_| exit_with_usage());
+        ecs.set_laser_targeting(mode);
+    }
+
+    if let Some(mut settings) = ecs.miner_settings() {
+        if args.iter().any(|arg| arg == "--auto-mining") {
+            settings.automatic = true;
+        }
+        if let Some(size) = option("--mining-salvo") {
+            settings.salvo_size = size.parse().unwrap_or_else(|_| exit_with_usage());
+        }
+        ecs.set_miner_settings(settings);
+    }
+
+    for _ in 0..ticks {
+        ecs.update();
+    }
+
+    if let Some(path) = option("--save") {
+        ecs.save(path).expect("Unable to write save file");
+    }
+
+    if let Some(path) = option("--export-blueprint") {
+        ecs.export_blueprint(path)
+            .expect("Unable to write blueprint file");
+    }
+
+    let entities = ecs.world.entities();
+    let asteroids = ecs.world.read_component::<entity::objects::Asteroid>();
+    let ships = ecs.world.read_component::<entity::Ship>();
+    let atmospheres = ecs.world.read_component::<entity::atmosphere::Atmosphere>();
+    let crews = ecs.world.read_component::<entity::crew::Crew>();
+    let queues = ecs.world.read_component::<entity::jobs::JobQueue>();
+    let block_entities = ecs.world.read_component::<entity::BlockEntity>();
+    let lasers = ecs.world.read_component::<block::Laser>();
+    let miners = ecs.world.read_component::<block::Miner>();
+    let healths = ecs.world.read_component::<entity::objects::Health>();
+    let blocks = ecs.get_resource::<block::Blocks>();
+    let inventory = ecs.get_resource::<item::Inventory>();
+
+    println!("[Headless] Simulated {} ticks", ticks);
+    println!("[Headless] Entities: {}", entities.join().count());
+    println!(
+        "[Headless] Asteroids: {}",
+        (&entities, &asteroids).join().count()
+    );
+    println!(
+        "[Headless] Models: {}",
+        ecs.get_resource::<MeshManager>().model_count()
+    );
+    let player_ship = ecs.get_resource::<entity::PlayerShip>().0;
+    let bounds = ships.get(player_ship).and_then(entity::Ship::bounding_box);
+    if let Some(bounds) = bounds {
+        let size = bounds.size();
+        println!(
+            "[Headless] Ship size: {}x{} tiles, from ({}, {}) to ({}, {})",
+            size.x, size.y, bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y
+        );
+    }
+    for (i, room) in atmospheres
+        .get(player_ship)
+        .map_or(&[][..], |atmosphere| &atmosphere.rooms)
+        .iter()
+        .enumerate()
+    {
+        println!(
+            "[Headless] Room {}: {} tiles, {} breaches, {:.0}% oxygen",
+            i + 1,
+            room.tiles.len(),
+            room.breaches.len(),
+            room.oxygen * 100.0
+        );
+    }
+    let crew_entities: Vec<_> = (&entities, &crews)
+        .join()
+        .map(|(entity, _)| entity)
+        .collect();
+    for (i, crew) in crews.join().enumerate() {
+        println!(
+            "[Headless] Crew {}: {:?} at ({:.1}, {:.1}), {:.0}% breath",
+            i + 1,
+            crew.state,
+            crew.position.x,
+            crew.position.y,
+            crew.breath * 100.0
+        );
+    }
+    for job in queues
+        .get(player_ship)
+        .map_or(&[][..], entity::jobs::JobQueue::jobs)
+    {
+        let block = match block_entities.get(job.target) {
+            Some(block_entity) => block_entity,
+            None => continue,
+        };
+        let worker = job
+            .worker
+            .and_then(|worker| crew_entities.iter().position(|crew| *crew == worker))
+            .map_or("waiting".to_string(), |i| format!("crew {}", i + 1));
+        println!(
+            "[Headless] Job: {:?} {} at ({}, {}), {}",
+            job.kind,
+            blocks.get_block(block.block_id()).type_name,
+            block.root().x,
+            block.root().y,
+            worker
+        );
+    }
+    for (laser, block) in (&lasers, &block_entities).join() {
+        let target = laser
+            .target
+            .and_then(|target| healths.get(target))
+            .map_or("no target".to_string(), |health| {
+                format!("targeting an asteroid with {} health", health.health())
+            });
+        println!(
+            "[Headless] Laser at ({}, {}): {:?}, {}",
+            block.root().x,
+            block.root().y,
+            laser.mode,
+            target
+        );
+    }
+    for (miner, block) in (&miners, &block_entities).join() {
+        println!(
+            "[Headless] Miner at ({}, {}): {}, {} asteroids queued",
+            block.root().x,
+            block.root().y,
+            if miner.settings.automatic {
+                "automatic"
+            } else {
+                "manual"
+            },
+            miner.queue().len()
+        );
+    }
+    for item in item::GameItem::iter() {
+        println!("[Headless] {:?}: {}", item, inventory.amount(item));
+    }
+}
+
+/// Prints how the headless mode is used and exits, for arguments that can't be parsed
+fn exit_with_usage() -> ! {
+    eprintln!("{}", HEADLESS_USAGE);
+    std::process::exit(1);
 }
 
 #[allow(dead_code)]
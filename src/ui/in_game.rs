@@ -1,11 +1,22 @@
 use super::{widgets::Button, widgets::Label, *};
-use crate::entity::{InputAction, InputManager};
+use crate::block::Blocks;
+use crate::entity::{
+    crew::Crew, jobs::JobQueue, power::PowerStatus, BlockEntity, BuildMode, BuildSelection,
+    InputAction, InputManager, PlayerShip,
+};
+use crate::floor::Floors;
 use crate::item::{GameItem, Inventory};
+use specs::{Join, WorldExt};
+
+/// The biggest salvo that the miner buttons go up to
+const MAX_SALVO_SIZE: u32 = 3;
+/// The number of jobs listed under the job count. The rest are only counted.
+const LISTED_JOBS: usize = 6;
 
 // TODO: Create a container with no size so that
 // all of the elements of a scene can be deleted at
 // once
-pub fn create_in_game_ui(ui: &mut Ui) {
+pub fn create_in_game_ui(ui: &mut Ui, ecs: &ECS) {
     let top_left_anchor = layout::WindowAnchor::TopLeft.new(ui);
     let inventory = layout::create_vbox(ui, Some(top_left_anchor), false);
 
@@ -32,6 +43,134 @@ pub fn create_in_game_ui(ui: &mut Ui) {
         );
     }
 
+    // The balance of the ship's power grids, which turns red when there isn't enough power
+    let power_label = Label::create(ui, Some(inventory), "Power: 0");
+    ui.set_on_update(
+        power_label,
+        Rc::new(move |ui, ecs| {
+            let power = ecs.get_resource::<PowerStatus>();
+            Label::update_text(
+                ui,
+                power_label,
+                &format!(
+                    "Power: {:+.1}/s (Battery {:.0}/{:.0})",
+                    power.generation - power.demand,
+                    power.stored,
+                    power.capacity
+                ),
+            );
+            let shortage = power.demand > power.generation && power.stored <= 0.0;
+            Label::set_color(
+                ui,
+                power_label,
+                if shortage { Color::RED } else { Color::WHITE },
+            );
+        }),
+    );
+
+    // How many of the ship's jobs the crew are working on
+    let jobs_label = Label::create(ui, Some(inventory), "Jobs: 0 being done, 0 waiting");
+    ui.set_on_update(
+        jobs_label,
+        Rc::new(move |ui, ecs| {
+            let player_ship = ecs.get_resource::<PlayerShip>().0;
+            let queues = ecs.world.read_component::<JobQueue>();
+            let jobs = queues.get(player_ship).map_or(&[][..], JobQueue::jobs);
+            let active = jobs.iter().filter(|job| job.worker.is_some()).count();
+            Label::update_text(
+                ui,
+                jobs_label,
+                &format!(
+                    "Jobs: {} being done, {} waiting",
+                    active,
+                    jobs.len() - active
+                ),
+            );
+        }),
+    );
+
+    // Who is doing what, in the order that the jobs are handed out
+    for index in 0..LISTED_JOBS {
+        let job_label = Label::create(ui, Some(inventory), "");
+        ui.set_on_update(
+            job_label,
+            Rc::new(move |ui, ecs| {
+                let player_ship = ecs.get_resource::<PlayerShip>().0;
+                let queues = ecs.world.read_component::<JobQueue>();
+                let jobs = queues.get(player_ship).map_or(&[][..], JobQueue::jobs);
+                let block_entities = ecs.world.read_component::<BlockEntity>();
+                let blocks = ecs.get_resource::<Blocks>();
+                let entities = ecs.world.entities();
+                let crews = ecs.world.read_component::<Crew>();
+
+                let text = match jobs.get(index) {
+                    Some(_) if index == LISTED_JOBS - 1 && jobs.len() > LISTED_JOBS => {
+                        format!("...and {} more", jobs.len() - index)
+                    }
+                    Some(job) => {
+                        let target = block_entities.get(job.target).map_or(
+                            "a missing block".to_string(),
+                            |block| {
+                                format!(
+                                    "{} at ({}, {})",
+                                    blocks.get_block(block.block_id()).type_name,
+                                    block.root().x,
+                                    block.root().y
+                                )
+                            },
+                        );
+                        // Crew members are numbered in the order that they were created
+                        let worker = job
+                            .worker
+                            .and_then(|worker| {
+                                (&entities, &crews)
+                                    .join()
+                                    .position(|(crew, _)| crew == worker)
+                            })
+                            .map_or("waiting".to_string(), |i| format!("crew {}", i + 1));
+                        format!("{:?} {}: {}", job.kind, target, worker)
+                    }
+                    None => String::new(),
+                };
+                Label::update_text(ui, job_label, &text);
+            }),
+        );
+    }
+
+    // How the lasers on the ship pick what to shoot at
+    let laser_label = Label::create(ui, Some(inventory), "Lasers: None");
+    ui.set_on_update(
+        laser_label,
+        Rc::new(move |ui, ecs| {
+            let text = match ecs.laser_targeting() {
+                Some(mode) => format!("Lasers: {:?}", mode),
+                None => "Lasers: None".to_string(),
+            };
+            Label::update_text(ui, laser_label, &text);
+        }),
+    );
+
+    // How the miners on the ship pick what to fire at
+    let miner_label = Label::create(ui, Some(inventory), "Miners: None");
+    ui.set_on_update(
+        miner_label,
+        Rc::new(move |ui, ecs| {
+            let text = match ecs.miner_settings() {
+                Some(settings) if settings.automatic => format!(
+                    "Miners: Automatic ({} ore, {} every {:.1}s)",
+                    settings
+                        .ore
+                        .map_or("any".to_string(), |ore| format!("{:?}", ore)),
+                    settings.salvo_size,
+                    settings.cooldown
+                ),
+                Some(_) => "Miners: Manual".to_string(),
+                None => "Miners: None".to_string(),
+            };
+            Label::update_text(ui, miner_label, &text);
+        }),
+    );
+
     let top_anchor = layout::WindowAnchor::TopCenter.new(ui);
     let action_label = Label::create(ui, Some(top_anchor), "Current Action: None");
     ui.set_on_update(
@@ -61,6 +200,56 @@ pub fn create_in_game_ui(ui: &mut Ui) {
         "Start Mining",
         Rc::new(|_, ecs| ecs.get_resource_mut::<InputManager>().action = InputAction::Mining),
     );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "Lock Doors",
+        Rc::new(|_, ecs| ecs.get_resource_mut::<InputManager>().action = InputAction::Doors),
+    );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "Laser Targeting",
+        Rc::new(|_, ecs| ecs.cycle_laser_targeting()),
+    );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "Automatic Mining",
+        Rc::new(|_, ecs| {
+            if let Some(mut settings) = ecs.miner_settings() {
+                settings.automatic = !settings.automatic;
+                ecs.set_miner_settings(settings);
+            }
+        }),
+    );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "Mining Ore",
+        Rc::new(|_, ecs| {
+            if let Some(mut settings) = ecs.miner_settings() {
+                // Goes through every ore in turn, then back to any ore
+                let mut ores = GameItem::iter().copied();
+                settings.ore = match settings.ore {
+                    None => ores.next(),
+                    Some(ore) => ores.skip_while(|other| *other != ore).nth(1),
+                };
+                ecs.set_miner_settings(settings);
+            }
+        }),
+    );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "Mining Salvo",
+        Rc::new(|_, ecs| {
+            if let Some(mut settings) = ecs.miner_settings() {
+                settings.salvo_size = settings.salvo_size % MAX_SALVO_SIZE + 1;
+                ecs.set_miner_settings(settings);
+            }
+        }),
+    );
     Button::create(
         ui,
         Some(button_stack),
@@ -75,4 +264,75 @@ pub fn create_in_game_ui(ui: &mut Ui) {
             ui.remove_node(button_stack);
         }),
     );
+
+    create_build_palette(ui, ecs);
+}
+
+/// A list of buttons for choosing what to build
+fn create_build_palette(ui: &mut Ui, ecs: &ECS) {
+    let top_right_anchor = layout::WindowAnchor::TopRight.new(ui);
+    let palette = layout::create_vbox(ui, Some(top_right_anchor), false);
+    let select = |selection: BuildSelection| -> EventHandler {
+        Rc::new(move |_, ecs| {
+            ecs.get_resource_mut::<BuildMode>().selection = Some(selection);
+            ecs.get_resource_mut::<InputManager>().action = InputAction::Build;
+        })
+    };
+
+    for block in ecs.get_resource::<Blocks>().iter() {
+        let hbox = layout::create_hbox(ui, Some(palette), false);
+        Button::create(
+            ui,
+            Some(hbox),
+            &format!("Build {}", block.type_name),
+            select(BuildSelection::Block(block.id)),
+        );
+
+        // The cost turns red when the inventory can't pay for it
+        let cost = block
+            .cost
+            .iter()
+            .map(|(item, amount)| format!("{} {:?}", amount, item))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let cost_label = Label::create(ui, Some(hbox), &cost);
+        let block_id = block.id;
+        ui.set_on_update(
+            cost_label,
+            Rc::new(move |ui, ecs| {
+                let blocks = ecs.get_resource::<Blocks>();
+                let affordable = ecs
+                    .get_resource::<Inventory>()
+                    .check_items(&blocks.get_block(block_id).cost)
+                    .is_ok();
+                Label::set_color(
+                    ui,
+                    cost_label,
+                    if affordable { Color::WHITE } else { Color::RED },
+                );
+            }),
+        );
+    }
+    for floor in ecs.get_resource::<Floors>().iter() {
+        Button::create(
+            ui,
+            Some(palette),
+            &format!("Build {} floor", floor.name),
+            select(BuildSelection::Floor(floor)),
+        );
+    }
+
+    let history = layout::create_hbox(ui, Some(palette), false);
+    Button::create(
+        ui,
+        Some(history),
+        "Undo",
+        Rc::new(|_, ecs| ecs.undo_build()),
+    );
+    Button::create(
+        ui,
+        Some(history),
+        "Redo",
+        Rc::new(|_, ecs| ecs.redo_build()),
+    );
 }
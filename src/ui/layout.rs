@@ -120,10 +120,15 @@ pub fn create_hbox(ui: &mut Ui, parent: Option<NodeId>, draw_background: bool) -
     )
 }
 
+// Every anchor so far happens to be along the top edge, which clippy mistakes for a
+// redundant prefix. The names follow the nine-patch positions, and will stay accurate
+// once the middle and bottom anchors are added.
+#[allow(clippy::enum_variant_names)]
 pub enum WindowAnchor {
     // TODO: Add the rest of the variants
     TopLeft,
     TopCenter,
+    TopRight,
 }
 
 impl WindowAnchor {
@@ -169,6 +174,10 @@ impl NodeHandler for WindowAnchor {
                 geometry.pos.x = 0.0;
                 geometry.pos.y = window_size.y - geometry.size.y;
             }),
+            Self::TopRight => layout(|geometry, window_size| {
+                geometry.pos.x = window_size.x - geometry.size.x;
+                geometry.pos.y = window_size.y - geometry.size.y;
+            }),
         }
     }
 }
@@ -106,7 +106,7 @@ pub struct Ui {
 }
 
 impl Ui {
-    pub fn new(assets: UiAssets) -> Self {
+    pub fn new(assets: UiAssets, ecs: &ECS) -> Self {
         let mut ui = Self {
             geometries: Arena::new(),
             layouts: WidgetLayouts::new(),
@@ -121,7 +121,7 @@ impl Ui {
             assets,
         };
 
-        in_game::create_in_game_ui(&mut ui);
+        in_game::create_in_game_ui(&mut ui, ecs);
 
         ui
     }
@@ -637,6 +637,13 @@ impl Color {
         a: 1.0,
     };
 
+    pub const RED: Self = Self {
+        r: 1.0,
+        g: 0.2,
+        b: 0.2,
+        a: 1.0,
+    };
+
     #[allow(dead_code)]
     pub const BLACK: Self = Self {
         r: 0.0,
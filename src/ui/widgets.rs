@@ -139,6 +139,11 @@ impl Label {
         let state = ui.states.get_mut::<Label>(node).unwrap();
         *state.text.borrow_mut() = text;
     }
+
+    pub fn set_color(ui: &mut Ui, node: NodeId, color: Color) {
+        let state = ui.states.get_mut::<Label>(node).unwrap();
+        state.text.borrow_mut().color = color;
+    }
 }
 
 struct LabelRenderer;